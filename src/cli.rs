@@ -0,0 +1,272 @@
+//! Command-line interface definition.
+//!
+//! Every command and option is declared here with `clap`, so invalid input is rejected
+//! with an error before any file is touched, and `--help` works on every command.
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use globset::Glob;
+use intelligent_file_deduplicator::{
+    filter::{FilterOptions, compile_globs, parse_size},
+    hashing::HashAlgorithm,
+    keep_policy::KeepPolicy,
+    report::ReportFormat,
+    safe_delete::DuplicateAction,
+    scanner::SymlinkPolicy,
+    stats::DEFAULT_TOP,
+};
+use regex::Regex;
+use std::path::PathBuf;
+
+/// Finds duplicate files and removes them safely.
+#[derive(Debug, Parser)]
+#[command(name = "hashlaser", bin_name = "hashlaser", version)]
+pub struct Cli {
+    /// Output format. json and ndjson write records to stdout and everything else to stderr.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub format: OutputFormat,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// How results are written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored text for people.
+    #[default]
+    Text,
+    /// One JSON array holding every record, written when the command finishes.
+    Json,
+    /// One JSON record per line, written as the command runs.
+    Ndjson,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// 🔍 Compare two files
+    Compare(CompareArgs),
+    /// 🧪 Scan directories for duplicates
+    Scan(ScanOptions),
+    /// 📄 Generate a report: JSON (can be applied), CSV, Markdown or HTML
+    Report(ReportArgs),
+    /// 🗑️ Delete duplicate files
+    Delete(DeleteArgs),
+    /// 📋 Execute a reviewed report
+    Apply(ApplyArgs),
+    /// 🎯 Scan with filtering
+    Filter(ScanOptions),
+    /// 📊 Show how much space duplicates waste, and where
+    Stats(StatsArgs),
+    /// ⏪ Undo a delete run from its journal
+    Restore {
+        /// Journal written by `delete` or `apply`.
+        journal: PathBuf,
+    },
+    /// 💾 Inspect or maintain the hash cache
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// Print a shell completion script
+    Completions {
+        /// Shell to generate completions for.
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct CompareArgs {
+    pub file1: PathBuf,
+    pub file2: PathBuf,
+    /// Hash algorithm: sha256, blake3 or xxh3.
+    #[arg(long = "hash", default_value_t)]
+    pub algorithm: HashAlgorithm,
+}
+
+/// Which directories to scan and how.
+#[derive(Debug, Args)]
+pub struct ScanOptions {
+    /// Directories to scan.
+    #[arg(value_name = "DIR", required = true)]
+    pub roots: Vec<PathBuf>,
+    /// Skip files smaller than this, e.g. 4096, 10MB or 1.5GiB.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub min: Option<u64>,
+    /// Skip files larger than this, e.g. 4096, 10MB or 1.5GiB.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max: Option<u64>,
+    /// Only scan files with these extensions, e.g. txt,csv.
+    #[arg(long, value_name = "EXT", value_delimiter = ',')]
+    pub ext: Vec<String>,
+    /// Only scan files whose name matches this regular expression.
+    #[arg(long, value_name = "PATTERN")]
+    pub regex: Option<Regex>,
+    /// Hash algorithm: sha256, blake3 or xxh3.
+    #[arg(long = "hash", default_value_t)]
+    pub algorithm: HashAlgorithm,
+    /// Rehash everything, bypassing the hash cache.
+    #[arg(long)]
+    pub no_cache: bool,
+    /// What to do with symbolic links: skip, follow or report.
+    #[arg(long, default_value_t)]
+    pub symlinks: SymlinkPolicy,
+    /// Skip files and directories matching this glob (repeatable).
+    #[arg(long, value_name = "GLOB", value_parser = parse_glob)]
+    pub exclude: Vec<String>,
+    /// Skip directories matching this glob, without walking them (repeatable).
+    #[arg(long = "exclude-dir", value_name = "GLOB", value_parser = parse_glob)]
+    pub exclude_dirs: Vec<String>,
+    /// Honour .gitignore and .ignore files. .hashlaserignore files always apply.
+    #[arg(long)]
+    pub gitignore: bool,
+    /// Only report duplicates found under more than one DIR.
+    #[arg(long)]
+    pub cross_root: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    #[command(flatten)]
+    pub scan: ScanOptions,
+    /// File to write the report to.
+    #[arg(value_name = "OUTPUT")]
+    pub output: PathBuf,
+    /// Report format: json, csv, markdown or html (default: from OUTPUT's extension,
+    /// else json).
+    #[arg(long, value_name = "FORMAT")]
+    pub report_format: Option<ReportFormat>,
+    #[command(flatten)]
+    pub keep: KeepOption,
+    #[command(flatten)]
+    pub action: ActionOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub scan: ScanOptions,
+    /// Decides which copy counts as the original when attributing redundant bytes to
+    /// directories.
+    #[command(flatten)]
+    pub keep: KeepOption,
+    /// Number of largest groups and directories to list.
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_TOP)]
+    pub top: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct DeleteArgs {
+    #[command(flatten)]
+    pub scan: ScanOptions,
+    /// Only print what would be done.
+    #[arg(long)]
+    pub dry_run: bool,
+    #[command(flatten)]
+    pub keep: KeepOption,
+    #[command(flatten)]
+    pub action: ActionOptions,
+    /// Never touch files under this directory, and keep them over other copies
+    /// (repeatable).
+    #[arg(long, value_name = "DIR")]
+    pub reference: Vec<PathBuf>,
+    #[command(flatten)]
+    pub journal: JournalOption,
+}
+
+#[derive(Debug, Args)]
+pub struct ApplyArgs {
+    /// Report written by `report`, possibly edited since.
+    pub plan: PathBuf,
+    /// Only print what would be done.
+    #[arg(long)]
+    pub dry_run: bool,
+    #[command(flatten)]
+    pub journal: JournalOption,
+}
+
+#[derive(Debug, Args)]
+pub struct KeepOption {
+    /// Which copy survives: comma-separated rules tried in order, from oldest, newest,
+    /// shortest, longest, first and under:<dir>.
+    #[arg(long, value_name = "RULES", default_value_t)]
+    pub keep: KeepPolicy,
+}
+
+/// What happens to each duplicate that is not kept. Unlinked by default.
+#[derive(Debug, Args)]
+#[group(multiple = false)]
+pub struct ActionOptions {
+    /// Move duplicates to the desktop trash instead of unlinking them.
+    #[arg(long)]
+    pub trash: bool,
+    /// Replace each duplicate with a hard link to the kept copy.
+    #[arg(long)]
+    pub link: bool,
+    /// Share extents on btrfs/XFS; files stay independent.
+    #[arg(long)]
+    pub dedupe_extents: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct JournalOption {
+    /// Undo journal to write (default: a new file in $XDG_STATE_HOME/hashlaser/journals).
+    #[arg(long, value_name = "FILE")]
+    pub journal: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Show how many hashes are cached
+    Stats,
+    /// Drop entries for files that are gone or changed
+    Prune,
+    /// Delete the cache
+    Clear,
+}
+
+impl ScanOptions {
+    /// Builds the filter options. Fails if `--min` exceeds `--max`.
+    pub fn filters(&self) -> Result<FilterOptions, String> {
+        if let (Some(min), Some(max)) = (self.min, self.max)
+            && min > max
+        {
+            return Err(format!(
+                "--min ({min} bytes) is larger than --max ({max} bytes)"
+            ));
+        }
+        let globs = |patterns: &[String]| {
+            let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+            compile_globs(&patterns)
+        };
+        Ok(FilterOptions {
+            min_size: self.min,
+            max_size: self.max,
+            extensions: (!self.ext.is_empty())
+                .then(|| self.ext.iter().map(|ext| ext.trim().to_string()).collect()),
+            regex: self.regex.clone(),
+            symlinks: self.symlinks,
+            exclude: globs(&self.exclude)?,
+            exclude_dirs: globs(&self.exclude_dirs)?,
+            ignore_files: self.gitignore,
+        })
+    }
+}
+
+impl ActionOptions {
+    pub fn action(&self) -> DuplicateAction {
+        if self.trash {
+            DuplicateAction::Trash
+        } else if self.link {
+            DuplicateAction::Link
+        } else if self.dedupe_extents {
+            DuplicateAction::DedupeExtents
+        } else {
+            DuplicateAction::Delete
+        }
+    }
+}
+
+/// Checks that `pattern` is a valid glob.
+fn parse_glob(pattern: &str) -> Result<String, String> {
+    Glob::new(pattern)
+        .map(|_| pattern.to_string())
+        .map_err(|e| e.to_string())
+}
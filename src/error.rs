@@ -0,0 +1,91 @@
+//! The crate's error type.
+//!
+//! Scans and destructive runs keep going when a single path fails; the failures are
+//! collected as [`Error`]s alongside the results instead of being dropped.
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// A failure tied to one path.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or modifying the path was not permitted.
+    PermissionDenied { path: PathBuf, source: io::Error },
+    /// The path disappeared after it was listed.
+    Vanished { path: PathBuf },
+    /// The path cannot be used, e.g. because it has no file name.
+    InvalidPath { path: PathBuf, reason: String },
+    /// Any other I/O error.
+    Io { path: PathBuf, source: io::Error },
+}
+
+/// Result type using the crate's [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Classifies an I/O error that occurred on `path`.
+    pub fn io<P: Into<PathBuf>>(path: P, source: io::Error) -> Self {
+        let path = path.into();
+        match source.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied { path, source },
+            io::ErrorKind::NotFound => Error::Vanished { path },
+            _ => Error::Io { path, source },
+        }
+    }
+
+    /// Creates an [`Error::InvalidPath`].
+    pub fn invalid_path<P: Into<PathBuf>>(path: P, reason: impl Into<String>) -> Self {
+        Error::InvalidPath {
+            path: path.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// The path the error occurred on.
+    pub fn path(&self) -> &Path {
+        match self {
+            Error::PermissionDenied { path, .. }
+            | Error::Vanished { path }
+            | Error::InvalidPath { path, .. }
+            | Error::Io { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PermissionDenied { path, .. } => {
+                write!(f, "permission denied: {}", path.display())
+            }
+            Error::Vanished { path } => write!(f, "no such file or directory: {}", path.display()),
+            Error::InvalidPath { path, reason } => {
+                write!(f, "invalid path {}: {}", path.display(), reason)
+            }
+            Error::Io { path, source } => write!(f, "I/O error on {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::PermissionDenied { source, .. } | Error::Io { source, .. } => Some(source),
+            Error::Vanished { .. } | Error::InvalidPath { .. } => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match &err {
+            Error::PermissionDenied { .. } => io::ErrorKind::PermissionDenied,
+            Error::Vanished { .. } => io::ErrorKind::NotFound,
+            Error::InvalidPath { .. } => io::ErrorKind::InvalidInput,
+            Error::Io { source, .. } => source.kind(),
+        };
+        io::Error::new(kind, err)
+    }
+}
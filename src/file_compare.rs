@@ -1,12 +1,73 @@
-/// Imports the `hash_file` function from the `hashing` module within the current crate.
+/// Imports the hashing helpers from the `hashing` module within the current crate.
 ///
-/// The `hash_file` function is typically used to compute a hash value for a given file,
+/// The `hash_file_with` function is typically used to compute a hash value for a given file,
 /// which can be useful for tasks such as file comparison, deduplication, or integrity checking.
-use crate::hashing::hash_file;
-use std::io;
+use crate::{
+    error::Error,
+    hashing::{ContentHasher, Sha256Hasher, hash_file_with},
+};
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
 
-pub fn compare_files(path1: &str, path2: &str) -> io::Result<bool> {
-    let hash1 = hash_file(path1)?;
-    let hash2 = hash_file(path2)?;
-    Ok(hash1 == hash2)
+/// Compares two files by their SHA-256 hash.
+pub fn compare_files<P: AsRef<Path>, Q: AsRef<Path>>(path1: P, path2: Q) -> Result<bool, Error> {
+    compare_files_with(path1, path2, &Sha256Hasher)
+}
+
+/// Compares two files by their hash under the given algorithm. A failure names the
+/// file that could not be read.
+pub fn compare_files_with<P: AsRef<Path>, Q: AsRef<Path>>(
+    path1: P,
+    path2: Q,
+    hasher: &dyn ContentHasher,
+) -> Result<bool, Error> {
+    let hash = |path: &Path| hash_file_with(path, hasher).map_err(|e| Error::io(path, e));
+    Ok(hash(path1.as_ref())? == hash(path2.as_ref())?)
+}
+
+/// Compares two files byte by byte, stopping at the first difference.
+///
+/// Unlike [`compare_files`], this does not rely on a hash and so cannot be fooled by a
+/// collision or a stale digest. Files of different lengths are rejected without reading.
+pub fn files_identical<P: AsRef<Path>, Q: AsRef<Path>>(path1: P, path2: Q) -> io::Result<bool> {
+    let file1 = File::open(path1)?;
+    let file2 = File::open(path2)?;
+    if file1.metadata()?.len() != file2.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let mut reader1 = BufReader::new(file1);
+    let mut reader2 = BufReader::new(file2);
+    let mut buffer1 = [0u8; 8192];
+    let mut buffer2 = [0u8; 8192];
+
+    loop {
+        let bytes_read = read_full(&mut reader1, &mut buffer1)?;
+        if read_full(&mut reader2, &mut buffer2[..bytes_read.max(1)])? != bytes_read {
+            return Ok(false); // One file ended before the other
+        }
+        if bytes_read == 0 {
+            return Ok(true);
+        }
+        if buffer1[..bytes_read] != buffer2[..bytes_read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Fills `buffer` as far as possible, returning fewer bytes only at end of file.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
 }
@@ -1,4 +1,7 @@
+use crate::scanner::SymlinkPolicy;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{ffi::OsStr, fs, path::Path};
 
 /// Structure to hold filtering options
@@ -7,6 +10,14 @@ pub struct FilterOptions {
     pub max_size: Option<u64>,
     pub extensions: Option<Vec<String>>,
     pub regex: Option<Regex>,
+    /// How the directory walk treats symbolic links.
+    pub symlinks: SymlinkPolicy,
+    /// Files and directories the walk skips, directories with everything below them.
+    pub exclude: Option<Globs>,
+    /// Directories the walk skips with everything below them.
+    pub exclude_dirs: Option<Globs>,
+    /// Honour `.gitignore` and `.ignore` files. `.hashlaserignore` files always apply.
+    pub ignore_files: bool,
 }
 
 /// Implements filtering logic for file paths based on size, extension, and regex criteria.
@@ -24,21 +35,35 @@ impl FilterOptions {
         self.check_size(path) && self.check_extension(path) && self.check_regex(path)
     }
 
+    /// Checks if the walk should skip the entry at `path`, a directory if `is_dir`.
+    ///
+    /// Patterns are matched against the entry's name and against `relative`, its path
+    /// below the scanned directory, so both `*.tmp` and `build/cache` work.
+    pub fn excludes(&self, path: &Path, relative: Option<&Path>, is_dir: bool) -> bool {
+        let matches = |globs: &Globs| {
+            path.file_name()
+                .is_some_and(|name| globs.set.is_match(name))
+                || relative.is_some_and(|relative| globs.set.is_match(relative))
+        };
+        self.exclude.as_ref().is_some_and(matches)
+            || (is_dir && self.exclude_dirs.as_ref().is_some_and(matches))
+    }
+
     /// Checks if the file at the given `path` matches the size constraints.
     ///
     /// Returns `true` if the file size is within the specified minimum and maximum size (if set),
     /// or if no size constraints are specified.
     fn check_size(&self, path: &Path) -> bool {
         if let Ok(metadata) = fs::metadata(path) {
-            if let Some(min) = self.min_size {
-                if metadata.len() < min {
-                    return false;
-                }
+            if let Some(min) = self.min_size
+                && metadata.len() < min
+            {
+                return false;
             }
-            if let Some(max) = self.max_size {
-                if metadata.len() > max {
-                    return false;
-                }
+            if let Some(max) = self.max_size
+                && metadata.len() > max
+            {
+                return false;
             }
         }
         true
@@ -50,8 +75,8 @@ impl FilterOptions {
     /// or if no extension constraints are specified.
     fn check_extension(&self, path: &Path) -> bool {
         if let Some(ref exts) = self.extensions {
-            if let Some(ext) = path.extension().and_then(OsStr::to_str) {
-                return exts.iter().any(|e| e.eq_ignore_ascii_case(ext));
+            if let Some(ext) = path.extension().map(OsStr::to_string_lossy) {
+                return exts.iter().any(|e| e.eq_ignore_ascii_case(&ext));
             } else {
                 return false; // No extension
             }
@@ -65,8 +90,9 @@ impl FilterOptions {
     /// or if no regex constraint is specified.
     fn check_regex(&self, path: &Path) -> bool {
         if let Some(ref re) = self.regex {
-            if let Some(name) = path.file_name().and_then(OsStr::to_str) {
-                return re.is_match(name);
+            // Non-UTF-8 bytes in the name match as U+FFFD
+            if let Some(name) = path.file_name().map(OsStr::to_string_lossy) {
+                return re.is_match(&name);
             } else {
                 return false; // No file name
             }
@@ -75,37 +101,114 @@ impl FilterOptions {
     }
 }
 
-/// Helper to parse filtering options from CLI args
-pub fn parse_filter_options(args: &[String]) -> FilterOptions {
-    let mut options = FilterOptions::default();
-
-    let mut i = 0;
-    while i < args.len() {
-        match args[i].as_str() {
-            "--min" => {
-                i += 1;
-                options.min_size = args.get(i).and_then(|v| v.parse::<u64>().ok());
-            }
-            "--max" => {
-                i += 1;
-                options.max_size = args.get(i).and_then(|v| v.parse::<u64>().ok());
-            }
-            "--ext" => {
-                i += 1;
-                options.extensions = args
-                    .get(i)
-                    .map(|v| v.split(',').map(|s| s.trim().to_string()).collect());
-            }
-            "--regex" => {
-                i += 1;
-                options.regex = args.get(i).and_then(|v| Regex::new(v).ok());
-            }
-            _ => {}
+/// Parses a size such as `4096`, `10MB`, `1.5GiB` or `512k` into bytes.
+///
+/// Units are case-insensitive. `K`, `M`, `G` and `T`, optionally followed by `B`, are
+/// powers of 1000; `Ki`, `Mi`, `Gi` and `Ti`, optionally followed by `B`, are powers of
+/// 1024. A bare number, or one followed by `B`, is in bytes.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "ki" | "kib" => 1 << 10,
+        "mi" | "mib" => 1 << 20,
+        "gi" | "gib" => 1 << 30,
+        "ti" | "tib" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "invalid size '{size}' (expected a number of bytes, optionally followed by KB, MB, GB, TB, KiB, MiB, GiB or TiB)"
+            ));
         }
-        i += 1;
+    };
+    let too_large = || format!("size '{size}' is too large");
+
+    if let Ok(count) = number.parse::<u64>() {
+        return count.checked_mul(multiplier).ok_or_else(too_large);
+    }
+    // Fractions such as 1.5GB
+    let count: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{size}'"))?;
+    let bytes = (count * multiplier as f64).round();
+    if bytes >= u64::MAX as f64 {
+        return Err(too_large());
+    }
+    Ok(bytes as u64)
+}
+
+/// Compiled glob patterns, kept together with their source so reports can show them.
+#[derive(Debug, Clone)]
+pub struct Globs {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl Globs {
+    /// The patterns, as given to [`compile_globs`].
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
     }
+}
+
+/// Compiles glob `patterns` for [`FilterOptions::exclude`] or [`FilterOptions::exclude_dirs`].
+///
+/// Returns `None` if there are no patterns.
+pub fn compile_globs(patterns: &[&str]) -> Result<Option<Globs>, String> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| e.to_string())?);
+    }
+    let set = builder.build().map_err(|e| e.to_string())?;
+    Ok(Some(Globs {
+        patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+        set,
+    }))
+}
 
-    options
+/// The [`FilterOptions`] a scan used, in a form that can be written to a report.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterSettings {
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub extensions: Vec<String>,
+    pub regex: Option<String>,
+    pub symlinks: SymlinkPolicy,
+    pub exclude: Vec<String>,
+    pub exclude_dirs: Vec<String>,
+    pub ignore_files: bool,
+}
+
+impl FilterOptions {
+    /// Records these options as [`FilterSettings`].
+    pub fn settings(&self) -> FilterSettings {
+        let patterns = |globs: &Option<Globs>| {
+            globs
+                .as_ref()
+                .map(|globs| globs.patterns.clone())
+                .unwrap_or_default()
+        };
+        FilterSettings {
+            min_size: self.min_size,
+            max_size: self.max_size,
+            extensions: self.extensions.clone().unwrap_or_default(),
+            regex: self.regex.as_ref().map(|regex| regex.as_str().to_string()),
+            symlinks: self.symlinks,
+            exclude: patterns(&self.exclude),
+            exclude_dirs: patterns(&self.exclude_dirs),
+            ignore_files: self.ignore_files,
+        }
+    }
 }
 
 /// Default implementation: match all files
@@ -116,6 +219,10 @@ impl Default for FilterOptions {
             max_size: None,
             extensions: None,
             regex: None,
+            symlinks: SymlinkPolicy::default(),
+            exclude: None,
+            exclude_dirs: None,
+            ignore_files: false,
         }
     }
 }
@@ -0,0 +1,53 @@
+//! Filesystem helpers shared by the modules that keep state on disk.
+
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Returns `path` made absolute, or unchanged if the current directory is unavailable.
+pub(crate) fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Returns the XDG base directory named by `var`, falling back to `$HOME/<fallback>`
+/// when it is unset or empty.
+pub(crate) fn xdg_dir(var: &str, fallback: &str) -> io::Result<PathBuf> {
+    if let Some(dir) = env::var_os(var).filter(|v| !v.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = env::var_os("HOME").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("neither {var} nor HOME is set"),
+        )
+    })?;
+    Ok(PathBuf::from(home).join(fallback))
+}
+
+/// Replaces `dest` with a file built by `write` under a temporary name next to it,
+/// so `dest` never holds a partial file. `purpose` tells temporary names apart.
+///
+/// The temporary name is removed on failure, and also when the rename did nothing
+/// because it and `dest` were already names of the same inode.
+pub(crate) fn replace_atomically<F>(dest: &Path, purpose: &str, write: F) -> io::Result<()>
+where
+    F: FnOnce(&Path) -> io::Result<()>,
+{
+    let name = dest
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".hashlaser-{purpose}-{}", process::id()));
+    let temp = dest.with_file_name(temp_name);
+
+    let result = write(&temp).and_then(|()| fs::rename(&temp, dest));
+    if result.is_err() || fs::symlink_metadata(&temp).is_ok() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
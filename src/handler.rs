@@ -1,152 +1,632 @@
 //! This module provides the main handler functions for the intelligent file deduplicator application.
+//! Each takes the arguments of its command, already parsed and validated by [`crate::cli`].
 //!
 //! It re-exports core functionalities from submodules, including:
-//! - `compare_files`: For comparing files to detect duplicates.
-//! - `scan_directory_for_duplicates`: For scanning directories and identifying duplicate files.
-//! - `write_json_report`: For generating JSON reports of duplicate findings.
-//! - `parse_filter_options`: For parsing filter options to customize scanning behavior.
+//! - `compare_files_with`: For comparing files to detect duplicates.
+//! - `Scanner`: For scanning one or more directories and identifying duplicate files.
+//! - `HashCache`: For reusing the hashes of unchanged files across runs.
+//! - `write_report`: For generating JSON, CSV, Markdown or HTML reports of duplicate findings.
 //! - `delete_duplicates`: For safely deleting identified duplicate files.
+//! - `apply_plan`: For executing a reviewed JSON report.
+//! - `restore_journal`: For undoing a delete run from its journal.
+//! - `ScanStats`: For summarizing how much space duplicates waste.
 
-use crate::{
-    file_compare::compare_files, filter::parse_filter_options, report::write_json_report,
-    safe_delete::delete_duplicates, scanner::scan_directory_for_duplicates, ui::*,
+use crate::cli::{
+    ApplyArgs, CacheCommand, CompareArgs, DeleteArgs, JournalOption, ReportArgs, ScanOptions,
+    StatsArgs,
+};
+use crate::ui::*;
+use intelligent_file_deduplicator::{
+    error::Error,
+    file_compare::compare_files_with,
+    filter::FilterOptions,
+    hash_cache::{HashCache, default_cache_path},
+    hashing::HashAlgorithm,
+    journal::{default_journal_path, restore_journal},
+    report::{ReportFormat, read_plan, write_report},
+    safe_delete::{
+        DeleteEventHandler, DeleteOptions, DeleteSummary, DuplicateAction, apply_plan,
+        delete_duplicates,
+    },
+    scanner::{CancellationToken, ScanResult, Scanner},
+    stats::ScanStats,
 };
 
-use std::process;
+use std::{
+    fs, io, mem,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
-/// Handles the `compare` command
-pub fn handle_compare_command(args: &[String]) {
-    if args.len() != 4 {
-        print_error("Error: compare requires 2 file paths.");
-        process::exit(1);
-    }
+/// The exit status of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// No duplicates were found, the compared files are identical, or the command did
+    /// everything it was asked to.
+    Success = 0,
+    /// Duplicates were found, or the compared files differ.
+    Duplicates = 1,
+    /// The arguments are invalid. clap exits with this status too.
+    Usage = 2,
+    /// Some files could not be read or acted on, so the results are incomplete.
+    PartialFailure = 3,
+}
 
-    let file1 = &args[2];
-    let file2 = &args[3];
+/// Writes any held-back records and exits with `status`.
+fn fail(status: Status) -> ! {
+    exit(status as i32)
+}
 
-    match compare_files(file1, file2) {
-        Ok(true) => print_success("Files are identical."),
-        Ok(false) => print_warning("Files are different."),
-        Err(e) => print_error(&format!("Error comparing files: {e}")),
+/// Picks the undo journal of a destructive command.
+///
+/// Every real run gets an undo journal, at the `--journal` path or a fresh default path.
+/// Dry runs get none.
+fn journal_path(option: &JournalOption, dry_run: bool) -> Option<PathBuf> {
+    if dry_run {
+        return None;
+    }
+    match &option.journal {
+        Some(path) => Some(path.clone()),
+        None => match default_journal_path() {
+            Ok(path) => Some(path),
+            Err(e) => {
+                print_error(&format!("Cannot determine journal location: {e}"));
+                fail(Status::PartialFailure);
+            }
+        },
     }
 }
 
-/// Handles the `scan` command
-pub fn handle_scan_command(args: &[String]) {
-    if args.len() < 3 {
-        print_error("Error: scan requires a directory path.");
-        process::exit(1);
+/// Prints how many files a delete or apply run processed and skipped.
+fn print_delete_summary(summary: &DeleteSummary) {
+    emit(&Record::DeleteSummary {
+        processed: summary.processed,
+        skipped: summary.skipped,
+        errors: summary.errors.len(),
+    });
+    print_info(&format!(
+        "📊 {} processed, {} skipped, {} error(s)",
+        summary.processed,
+        summary.skipped,
+        summary.errors.len()
+    ));
+}
+
+/// Tells the user where the undo journal of a run went, if one was written. A journal
+/// with no entries has nothing to undo and is removed instead.
+fn print_journal_location(journal: Option<PathBuf>) {
+    let journal = journal.filter(|path| match fs::metadata(path) {
+        Ok(metadata) if metadata.len() == 0 => fs::remove_file(path).is_err(),
+        Ok(_) => true,
+        Err(_) => false,
+    });
+    if let Some(journal) = journal {
+        emit(&Record::Journal { path: &journal });
+        print_info(&format!(
+            "📝 Journal written to `{}` (undo with `hashlaser restore <journal>`)",
+            journal.display()
+        ));
     }
+}
 
-    let dir = &args[2];
-    let filter_args = &args[3..];
-    let filters = parse_filter_options(filter_args);
-    print_info(&format!("📁 Scanning directory: {}", dir));
-    let duplicates = scan_directory_for_duplicates(dir, &filters);
+/// Without a handler the library prints each step of a delete or apply run as text;
+/// in json and ndjson modes every step becomes a record instead.
+fn delete_events() -> Option<DeleteEventHandler> {
+    machine_readable().then(|| DeleteEventHandler::new(|event| emit(event)))
+}
 
-    if duplicates.is_empty() {
-        print_success("No duplicates found.");
-        return;
+/// Summarizes `errors` and picks the exit status: a partial failure if there are any,
+/// otherwise [`Status::Duplicates`] if `found` is set.
+fn finish(found: bool, errors: &[Error]) -> Status {
+    if !errors.is_empty() {
+        print_error_summary(errors);
+        Status::PartialFailure
+    } else if found {
+        Status::Duplicates
+    } else {
+        Status::Success
     }
+}
 
-    let mut found = false;
-    println!("🔍 Duplicate files found:");
-    for (hash, files) in &duplicates {
-        if files.len() > 1 {
-            found = true;
-            println!("\n🧬 Hash: {}", hash);
-            print_list(files);
+/// Joins paths for display, e.g. `a`, `b`.
+fn join_paths(paths: &[PathBuf]) -> String {
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    paths.join("`, `")
+}
+
+/// Scans the directories of `options`, followed by `extra_roots`, and lists the symlinks
+/// found if `--symlinks report` is given.
+///
+/// With `--cross-root`, only groups spanning more than one root are kept.
+fn run_scan(options: &ScanOptions, extra_roots: &[PathBuf]) -> ScanResult {
+    let filters = options.filters().unwrap_or_else(|e| {
+        print_error(&format!("Error: {e}"));
+        fail(Status::Usage);
+    });
+    let mut roots = options.roots.clone();
+    roots.extend_from_slice(extra_roots);
+
+    let mut result = scan_with_cache_option(&roots, &filters, options.algorithm, options.no_cache);
+    if options.cross_root {
+        result.retain_cross_root();
+        print_info("🌉 Showing only duplicates found under more than one root");
+    }
+    if !result.symlinks.is_empty() {
+        print_info(&format!(
+            "🔗 {} symlink(s) not followed:",
+            result.symlinks.len()
+        ));
+        if machine_readable() {
+            for path in &result.symlinks {
+                emit(&Record::Symlink { path });
+            }
+        } else {
+            print_list(&result.symlinks);
         }
     }
+    result
+}
 
-    if !found {
-        print_success("No duplicate files matched the given filters.");
+/// Cancelled by Ctrl-C while a scan runs.
+static INTERRUPT: OnceLock<CancellationToken> = OnceLock::new();
+
+extern "C" fn cancel_scan(_signal: libc::c_int) {
+    if let Some(token) = INTERRUPT.get() {
+        token.cancel();
     }
 }
 
-/// Handles the `report` command
-pub fn handle_report_command(args: &[String]) {
-    if args.len() < 4 {
-        print_error("Error: report requires a directory and an output file.");
-        process::exit(1);
+/// Runs `scanner` with a live progress display on stderr.
+///
+/// Ctrl-C cancels the scan rather than killing the process, so the groups confirmed so
+/// far can still be reported.
+fn run_with_progress(scanner: Scanner<'_>) -> ScanResult {
+    let token = INTERRUPT.get_or_init(CancellationToken::new).clone();
+    let progress = ScanProgress::new();
+    let scanner = scanner
+        .cancel_token(token)
+        .on_event(|event| progress.update(event));
+
+    // The handler only sets an atomic flag
+    let previous = unsafe {
+        libc::signal(
+            libc::SIGINT,
+            cancel_scan as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    };
+    let result = scanner.run();
+    unsafe { libc::signal(libc::SIGINT, previous) };
+
+    progress.finish();
+    if result.cancelled {
+        print_warning(&format!(
+            "Scan interrupted: only the {} group(s) confirmed so far are included",
+            result.groups.len()
+        ));
     }
+    result
+}
 
-    let dir = &args[2];
-    let output_path = &args[3];
-    let filter_args = &args[4..];
-    let filters = parse_filter_options(filter_args);
-    print_info(&format!("📄 Generating report for: {}", dir));
-    let duplicates = scan_directory_for_duplicates(dir, &filters);
+/// Scans `roots` through the persistent hash cache, unless `--no-cache` is given.
+///
+/// A cache that cannot be read is replaced; one that cannot be written only costs
+/// a warning.
+fn scan_with_cache_option(
+    roots: &[PathBuf],
+    filters: &FilterOptions,
+    algorithm: HashAlgorithm,
+    no_cache: bool,
+) -> ScanResult {
+    let scanner = Scanner::new(roots).filters(filters).algorithm(algorithm);
+    if no_cache {
+        return run_with_progress(scanner);
+    }
+    let Ok(path) = default_cache_path() else {
+        return run_with_progress(scanner);
+    };
+    let cache = HashCache::open(&path).unwrap_or_else(|e| {
+        print_warning(&format!(
+            "Ignoring unreadable hash cache `{}`: {e}",
+            path.display()
+        ));
+        HashCache::new(&path)
+    });
 
-    match write_json_report(&duplicates, output_path) {
-        Ok(_) => print_success(&format!("Report saved to `{}`", output_path)),
-        Err(e) => print_error(&format!("Failed to write report: {e}")),
+    let result = run_with_progress(scanner.cache(&cache));
+    print_info(&format!(
+        "💾 Hash cache: {} hit(s), {} miss(es)",
+        cache.hits(),
+        cache.misses()
+    ));
+    if let Err(e) = cache.save() {
+        print_warning(&format!(
+            "Could not save hash cache `{}`: {e}",
+            path.display()
+        ));
     }
+    result
 }
 
-/// Handles the `delete` command
-pub fn handle_delete_command(args: &[String]) {
-    if args.len() < 3 {
-        print_error("Error: delete requires a directory.");
-        process::exit(1);
+/// Prints the duplicate groups of a scan under `heading`, then the space they waste.
+fn print_groups(heading: &str, result: &ScanResult) {
+    if !machine_readable() {
+        println!("{heading}");
+    }
+    for group in &result.groups {
+        print_group(group);
+    }
+    emit_scan_summary(result);
+    print_reclaimable(&result.groups);
+}
+
+/// Writes the totals of a scan as a record.
+fn emit_scan_summary(result: &ScanResult) {
+    emit(&Record::ScanSummary {
+        groups: result.groups.len(),
+        reclaimable_bytes: result
+            .groups
+            .iter()
+            .map(|group| group.reclaimable_bytes())
+            .sum(),
+        cancelled: result.cancelled,
+    });
+}
+
+/// Handles the `compare` command
+pub fn handle_compare_command(args: &CompareArgs) -> Status {
+    let verdict = compare_files_with(&args.file1, &args.file2, args.algorithm.hasher());
+    if let Ok(identical) = verdict {
+        emit(&Record::Compare {
+            file1: &args.file1,
+            file2: &args.file2,
+            algorithm: args.algorithm,
+            identical,
+        });
+    }
+    match verdict {
+        Ok(true) => {
+            print_success("Files are identical.");
+            Status::Success
+        }
+        Ok(false) => {
+            print_warning("Files are different.");
+            Status::Duplicates
+        }
+        Err(error) => {
+            emit(&Record::Error {
+                path: error.path(),
+                message: error.to_string(),
+            });
+            print_error(&format!("Error comparing files: {error}"));
+            Status::PartialFailure
+        }
+    }
+}
+
+/// Handles the `scan` command
+pub fn handle_scan_command(options: &ScanOptions) -> Status {
+    print_info(&format!(
+        "📁 Scanning directory: `{}` ({})",
+        join_paths(&options.roots),
+        options.algorithm
+    ));
+    let result = run_scan(options, &[]);
+    print_stages(&result.stages);
+
+    if result.groups.is_empty() {
+        emit_scan_summary(&result);
+        print_success("No duplicates found.");
+        return finish(false, &result.errors);
+    }
+
+    print_groups("🔍 Duplicate files found:", &result);
+    finish(true, &result.errors)
+}
+
+/// Handles the `report` command
+pub fn handle_report_command(args: &ReportArgs) -> Status {
+    let keep = &args.keep.keep;
+    let format = args
+        .report_format
+        .or_else(|| ReportFormat::from_extension(&args.output))
+        .unwrap_or_default();
+    print_info(&format!(
+        "📄 Generating {} report for: `{}`",
+        format,
+        join_paths(&args.scan.roots)
+    ));
+    print_info(&format!("📌 Keep policy: {}", keep));
+    let result = run_scan(&args.scan, &[]);
+
+    match write_report(&result, keep, args.action.action(), format, &args.output) {
+        Ok(_) => {
+            emit(&Record::Report {
+                path: &args.output,
+                format,
+                groups: result.groups.len(),
+            });
+            print_success(&format!("Report saved to `{}`", args.output.display()))
+        }
+        Err(e) => {
+            print_error(&format!("Failed to write report: {e}"));
+            return Status::PartialFailure;
+        }
     }
+    finish(!result.groups.is_empty(), &result.errors)
+}
 
-    let dir = &args[2];
-    let dry_run = args.contains(&"--dry-run".to_string());
-    let filter_args: Vec<String> = args.iter().skip(3).cloned().collect();
-    let filters = parse_filter_options(&filter_args);
+/// Handles the `delete` command
+pub fn handle_delete_command(args: &DeleteArgs) -> Status {
+    let dry_run = args.dry_run;
+    let action = args.action.action();
+    let keep = args.keep.keep.clone();
 
     print_info(&format!(
-        "🧼 Deleting duplicates in `{}` (dry-run = {})",
-        dir, dry_run
+        "🧼 Deleting duplicates in `{}` (dry-run = {}, action = {:?})",
+        join_paths(&args.scan.roots),
+        dry_run,
+        action
     ));
+    print_info(&format!("📌 Keep policy: {}", keep));
+    if !args.reference.is_empty() {
+        print_info(&format!(
+            "🛡️ Protecting reference directories `{}`",
+            join_paths(&args.reference)
+        ));
+    }
 
-    let duplicates = scan_directory_for_duplicates(dir, &filters);
+    // Reference directories are scanned too, so their copies can be matched
+    let mut scan = run_scan(&args.scan, &args.reference);
+    if scan.cancelled {
+        print_error("Error: the scan was interrupted, so nothing was deleted.");
+        return Status::PartialFailure;
+    }
 
-    if duplicates.is_empty() {
+    if scan.groups.is_empty() {
         print_success("No duplicates to delete.");
-        return;
+        return finish(false, &scan.errors);
     }
 
-    match delete_duplicates(&duplicates, dry_run) {
-        Ok(_) => {
+    let journal = journal_path(&args.journal, dry_run);
+
+    let options = DeleteOptions {
+        dry_run,
+        action,
+        keep,
+        journal: journal.clone(),
+        reference: args.reference.clone(),
+        on_event: delete_events(),
+        ..DeleteOptions::default()
+    };
+
+    let mut errors = mem::take(&mut scan.errors);
+    let unsupported = match delete_duplicates(&scan, &options) {
+        Ok(summary) => {
+            print_delete_summary(&summary);
+            // Nothing could be done at all, rather than a few files being skipped
+            let unsupported = summary.processed == 0 && summary.unsupported > 0;
+            errors.extend(summary.errors);
             if dry_run {
                 print_success("Dry run complete. No files were deleted.");
+            } else if action == DuplicateAction::Trash {
+                print_success("Duplicate files moved to the trash.");
+            } else if action == DuplicateAction::Link {
+                print_success("Duplicate files replaced with hard links.");
+            } else if action == DuplicateAction::DedupeExtents && unsupported {
+                print_error(
+                    "Nothing was deduplicated: the filesystem does not support extent sharing.",
+                );
+            } else if action == DuplicateAction::DedupeExtents {
+                print_success("Extent deduplication finished.");
             } else {
                 print_success("Duplicate files deleted successfully.");
             }
+            unsupported
+        }
+        Err(e) => {
+            print_error(&format!("Failed to delete duplicates: {e}"));
+            print_journal_location(journal);
+            return Status::PartialFailure;
         }
-        Err(e) => print_error(&format!("Failed to delete duplicates: {e}")),
+    };
+
+    print_journal_location(journal);
+    if unsupported {
+        return Status::PartialFailure;
     }
+    finish(false, &errors)
 }
 
-/// Handles the `filter` command
-pub fn handle_filter_command(args: &[String]) {
-    if args.len() < 3 {
-        print_error("Error: filter requires a directory path.");
-        process::exit(1);
-    }
+/// Handles the `apply` command
+pub fn handle_apply_command(args: &ApplyArgs) -> Status {
+    let plan_path = &args.plan;
+    let dry_run = args.dry_run;
+    let journal = journal_path(&args.journal, dry_run);
+
+    let plan = match read_plan(plan_path) {
+        Ok(plan) => plan,
+        Err(e) => {
+            print_error(&format!(
+                "Failed to read plan `{}`: {e}",
+                plan_path.display()
+            ));
+            return Status::PartialFailure;
+        }
+    };
+    print_info(&format!(
+        "📋 Applying plan `{}` (dry-run = {}, {} group(s))",
+        plan_path.display(),
+        dry_run,
+        plan.duplicates.len()
+    ));
 
-    let dir = &args[2];
-    let filters = parse_filter_options(&args[3..]);
-    let filtered = scan_directory_for_duplicates(dir, &filters);
+    let options = DeleteOptions {
+        dry_run,
+        journal: journal.clone(),
+        on_event: delete_events(),
+        ..DeleteOptions::default()
+    };
 
-    if filtered.is_empty() {
-        print_success("No matching files found.");
-        return;
+    let summary = match apply_plan(&plan, &options) {
+        Ok(summary) => summary,
+        Err(e) => {
+            print_error(&format!("Failed to apply plan: {e}"));
+            print_journal_location(journal);
+            return Status::PartialFailure;
+        }
+    };
+    print_delete_summary(&summary);
+    if dry_run {
+        print_success("Dry run complete. No files were touched.");
+    } else {
+        print_success("Plan applied.");
     }
 
-    let mut found = false;
-    println!("🔍 Matching files:");
-    for (hash, files) in &filtered {
-        if files.len() > 1 {
-            found = true;
-            println!("\n🧬 Hash: {}", hash);
-            print_list(files);
+    print_journal_location(journal);
+    finish(false, &summary.errors)
+}
+
+/// Handles the `restore` command
+pub fn handle_restore_command(journal: &Path) -> Status {
+    print_info(&format!("⏪ Restoring from journal: {}", journal.display()));
+
+    let summary = match restore_journal(journal) {
+        Ok(summary) => summary,
+        Err(e) => {
+            print_error(&format!("Failed to read journal: {e}"));
+            return Status::PartialFailure;
         }
+    };
+
+    if machine_readable() {
+        for path in &summary.restored {
+            emit(&Record::Restored { path });
+        }
+    } else if !summary.restored.is_empty() {
+        println!("♻️ Restored:");
+        print_list(&summary.restored);
+    }
+    for (path, reason) in &summary.unrestorable {
+        emit(&Record::Unrestorable { path, reason });
+        print_warning(&format!("Could not restore {}: {}", path.display(), reason));
+    }
+
+    if summary.unrestorable.is_empty() {
+        print_success(&format!("Restored {} file(s).", summary.restored.len()));
+        Status::Success
+    } else {
+        print_error(&format!(
+            "Restored {} file(s); {} could not be restored.",
+            summary.restored.len(),
+            summary.unrestorable.len()
+        ));
+        Status::PartialFailure
     }
+}
+
+/// Handles the `filter` command
+pub fn handle_filter_command(options: &ScanOptions) -> Status {
+    let result = run_scan(options, &[]);
 
-    if !found {
+    if result.groups.is_empty() {
+        emit_scan_summary(&result);
         print_success("No duplicate files matched the given filters.");
+        return finish(false, &result.errors);
+    }
+
+    print_groups("🔍 Matching files:", &result);
+    finish(true, &result.errors)
+}
+
+/// Handles the `stats` command
+pub fn handle_stats_command(args: &StatsArgs) -> Status {
+    print_info(&format!(
+        "📊 Measuring duplicates in `{}`",
+        join_paths(&args.scan.roots)
+    ));
+    let result = run_scan(&args.scan, &[]);
+    let stats = ScanStats::from_scan(&result, &args.keep.keep, args.top);
+    print_stats(&stats);
+    finish(stats.groups > 0, &result.errors)
+}
+
+/// Handles the `cache stats|prune|clear` command
+pub fn handle_cache_command(command: &CacheCommand) -> Status {
+    let path = match default_cache_path() {
+        Ok(path) => path,
+        Err(e) => {
+            print_error(&format!("Cannot determine cache location: {e}"));
+            return Status::PartialFailure;
+        }
+    };
+    let open = || match HashCache::open(&path) {
+        Ok(cache) => cache,
+        Err(e) => {
+            print_error(&format!(
+                "Failed to read hash cache `{}`: {e}",
+                path.display()
+            ));
+            fail(Status::PartialFailure);
+        }
+    };
+
+    match command {
+        CacheCommand::Stats => {
+            let stats = open().stats();
+            if machine_readable() {
+                emit(&Record::CacheStats {
+                    path: &path,
+                    entries: stats.entries,
+                    by_algorithm: stats
+                        .by_algorithm
+                        .iter()
+                        .map(|&(algorithm, count)| (algorithm.name(), count))
+                        .collect(),
+                    file_size: stats.file_size,
+                });
+                return Status::Success;
+            }
+            print_info(&format!("💾 Hash cache: {}", path.display()));
+            println!("Entries: {}", stats.entries);
+            for (algorithm, count) in &stats.by_algorithm {
+                println!("  {}: {}", algorithm, count);
+            }
+            match stats.file_size {
+                Some(size) => println!("Size on disk: {} bytes", size),
+                None => println!("Size on disk: (not created yet)"),
+            }
+        }
+        CacheCommand::Prune => {
+            let cache = open();
+            let removed = cache.prune();
+            if let Err(e) = cache.save() {
+                print_error(&format!("Failed to save hash cache: {e}"));
+                return Status::PartialFailure;
+            }
+            emit(&Record::CachePruned { removed });
+            print_success(&format!(
+                "Pruned {} stale entr{}.",
+                removed,
+                if removed == 1 { "y" } else { "ies" }
+            ));
+        }
+        CacheCommand::Clear => match fs::remove_file(&path) {
+            Ok(()) => {
+                emit(&Record::CacheCleared { existed: true });
+                print_success("Hash cache cleared.")
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                emit(&Record::CacheCleared { existed: false });
+                print_success("Hash cache is already empty.")
+            }
+            Err(e) => {
+                print_error(&format!("Failed to clear hash cache: {e}"));
+                return Status::PartialFailure;
+            }
+        },
     }
+    Status::Success
 }
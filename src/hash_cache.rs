@@ -0,0 +1,226 @@
+//! Persistent cache of full-file hashes.
+//!
+//! Each entry records a file's device, inode, size and mtime next to its hash. A later
+//! scan reuses the hash only if all four still match, so unchanged files are never
+//! re-read. The cache is a single JSON file, by default
+//! `$XDG_CACHE_HOME/hashlaser/hashes.json`, loaded in full and rewritten atomically.
+
+use crate::{
+    fs_util::{absolute, replace_atomically, xdg_dir},
+    hashing::{HashAlgorithm, hash_file_with},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+/// Version of the on-disk format. Caches with another version are ignored.
+const CACHE_VERSION: u32 = 1;
+
+/// Files modified this recently are hashed but not cached: a write landing in the same
+/// mtime tick as our read would otherwise go unnoticed.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// The identity of a file's contents at the time it was hashed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Absolute path of the file.
+    #[serde(with = "crate::path_encoding")]
+    pub path: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub dev: u64,
+    pub ino: u64,
+    pub size: u64,
+    pub modified: SystemTime,
+    pub hash: String,
+}
+
+/// Summary returned by [`HashCache::stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of cached hashes.
+    pub entries: usize,
+    /// Number of cached hashes per algorithm.
+    pub by_algorithm: Vec<(HashAlgorithm, usize)>,
+    /// Size of the cache file on disk, if it exists.
+    pub file_size: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: Vec<CacheEntry>,
+}
+
+/// An in-memory hash cache backed by a file. Safe to share between scanning threads.
+#[derive(Debug)]
+pub struct HashCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<(HashAlgorithm, PathBuf), CacheEntry>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl HashCache {
+    /// Creates an empty cache that will be saved to `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        HashCache {
+            path: path.as_ref().to_path_buf(),
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Loads the cache stored at `path`. A missing file gives an empty cache; an
+    /// unreadable or corrupt one is an error.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let cache = HashCache::new(&path);
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(cache),
+            Err(e) => return Err(e),
+        };
+        let file: CacheFile = serde_json::from_str(&json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if file.version == CACHE_VERSION {
+            let mut entries = cache.lock();
+            for entry in file.entries {
+                entries.insert((entry.algorithm, entry.path.clone()), entry);
+            }
+        }
+        Ok(cache)
+    }
+
+    /// Returns the file the cache is saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Hashes the file at `path` with `algorithm`, reusing the cached hash if the file's
+    /// device, inode, size and mtime are unchanged, and caching a freshly computed one.
+    pub fn hash_file(&self, path: &Path, algorithm: HashAlgorithm) -> io::Result<String> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        let key = (algorithm, absolute(path));
+
+        if let Some(entry) = self.lock().get(&key)
+            && entry.dev == metadata.dev()
+            && entry.ino == metadata.ino()
+            && entry.size == metadata.len()
+            && entry.modified == modified
+        {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(entry.hash.clone());
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let hash = hash_file_with(path, algorithm.hasher())?;
+
+        let settled = SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|age| age >= RACY_WINDOW);
+        if settled {
+            let entry = CacheEntry {
+                path: key.1.clone(),
+                algorithm,
+                dev: metadata.dev(),
+                ino: metadata.ino(),
+                size: metadata.len(),
+                modified,
+                hash: hash.clone(),
+            };
+            self.lock().insert(key, entry);
+        }
+        Ok(hash)
+    }
+
+    /// Number of hashes served from the cache since it was opened.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of files hashed because they were missing from the cache or had changed.
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Counts the cached entries.
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.lock();
+        let mut by_algorithm: HashMap<HashAlgorithm, usize> = HashMap::new();
+        for (algorithm, _) in entries.keys() {
+            *by_algorithm.entry(*algorithm).or_default() += 1;
+        }
+        let mut by_algorithm: Vec<_> = by_algorithm.into_iter().collect();
+        by_algorithm.sort_by_key(|&(algorithm, _)| algorithm.name());
+
+        CacheStats {
+            entries: entries.len(),
+            by_algorithm,
+            file_size: fs::metadata(&self.path).ok().map(|m| m.len()),
+        }
+    }
+
+    /// Drops entries whose file is gone or no longer matches the recorded device, inode,
+    /// size or mtime. Returns the number of entries removed.
+    pub fn prune(&self) -> usize {
+        let mut entries = self.lock();
+        let before = entries.len();
+        entries.retain(|_, entry| match fs::metadata(&entry.path) {
+            Ok(metadata) => {
+                metadata.dev() == entry.dev
+                    && metadata.ino() == entry.ino
+                    && metadata.len() == entry.size
+                    && metadata.modified().ok() == Some(entry.modified)
+            }
+            Err(_) => false,
+        });
+        before - entries.len()
+    }
+
+    /// Drops every entry.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Writes the cache to its file, replacing it atomically.
+    pub fn save(&self) -> io::Result<()> {
+        let mut entries: Vec<CacheEntry> = self.lock().values().cloned().collect();
+        entries.sort_by(|a, b| (&a.path, a.algorithm.name()).cmp(&(&b.path, b.algorithm.name())));
+        let json = serde_json::to_vec(&CacheFile {
+            version: CACHE_VERSION,
+            entries,
+        })
+        .map_err(io::Error::other)?;
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        replace_atomically(&self.path, "cache", |temp| {
+            let mut file = File::create(temp)?;
+            file.write_all(&json)?;
+            file.sync_data()
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(HashAlgorithm, PathBuf), CacheEntry>> {
+        // A panic while holding the lock cannot leave a half-written entry behind
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Returns the default cache file, `$XDG_CACHE_HOME/hashlaser/hashes.json`, falling
+/// back to `$HOME/.cache` when `XDG_CACHE_HOME` is unset.
+pub fn default_cache_path() -> io::Result<PathBuf> {
+    Ok(xdg_dir("XDG_CACHE_HOME", ".cache")?.join("hashlaser/hashes.json"))
+}
@@ -1,36 +1,202 @@
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
+    fmt,
     fs::File,
-    io::{self, Read},
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    str::FromStr,
 };
+use xxhash_rust::xxh3::Xxh3;
+
+/// A content hash function that can digest a stream of bytes.
+///
+/// The scanner, [`crate::file_compare`] and the report all go through this trait,
+/// so the algorithm can be swapped without touching the callers.
+pub trait ContentHasher: Send + Sync {
+    /// Stable, lowercase name of the algorithm (e.g. `sha256`), as recorded in reports.
+    fn name(&self) -> &'static str;
+
+    /// Reads `reader` to the end and returns the hexadecimal digest of its contents.
+    fn hash_reader(&self, reader: &mut dyn Read) -> io::Result<String>;
+}
+
+/// SHA-256 from the `sha2` crate. Cryptographic; the default.
+pub struct Sha256Hasher;
+
+/// BLAKE3. Cryptographic and considerably faster than SHA-256.
+pub struct Blake3Hasher;
+
+/// 128-bit xxh3. Not cryptographic, but the fastest of the three.
+pub struct Xxh3Hasher;
+
+impl ContentHasher for Sha256Hasher {
+    fn name(&self) -> &'static str {
+        "sha256"
+    }
+
+    fn hash_reader(&self, reader: &mut dyn Read) -> io::Result<String> {
+        let mut hasher = Sha256::new();
+        read_chunks(reader, |chunk| hasher.update(chunk))?;
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+impl ContentHasher for Blake3Hasher {
+    fn name(&self) -> &'static str {
+        "blake3"
+    }
+
+    fn hash_reader(&self, reader: &mut dyn Read) -> io::Result<String> {
+        let mut hasher = blake3::Hasher::new();
+        read_chunks(reader, |chunk| {
+            hasher.update(chunk);
+        })?;
+        Ok(hasher.finalize().to_hex().to_string())
+    }
+}
+
+impl ContentHasher for Xxh3Hasher {
+    fn name(&self) -> &'static str {
+        "xxh3"
+    }
+
+    fn hash_reader(&self, reader: &mut dyn Read) -> io::Result<String> {
+        let mut hasher = Xxh3::new();
+        read_chunks(reader, |chunk| hasher.update(chunk))?;
+        Ok(format!("{:032x}", hasher.digest128()))
+    }
+}
+
+/// The hash algorithms selectable from the command line with `--hash`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+    Xxh3,
+}
+
+impl HashAlgorithm {
+    /// Returns the [`ContentHasher`] implementing this algorithm.
+    pub fn hasher(self) -> &'static dyn ContentHasher {
+        match self {
+            HashAlgorithm::Sha256 => &Sha256Hasher,
+            HashAlgorithm::Blake3 => &Blake3Hasher,
+            HashAlgorithm::Xxh3 => &Xxh3Hasher,
+        }
+    }
+
+    /// Returns the algorithm's name, e.g. `blake3`.
+    pub fn name(self) -> &'static str {
+        self.hasher().name()
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha256" | "sha-256" => Ok(HashAlgorithm::Sha256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "xxh3" => Ok(HashAlgorithm::Xxh3),
+            other => Err(format!(
+                "unknown hash algorithm '{other}' (expected sha256, blake3 or xxh3)"
+            )),
+        }
+    }
+}
 
 /// Computes the SHA-256 hash of the file at the given path.
 ///
 /// # Arguments
 ///
-/// * `path` - A string slice that holds the path to the file to be hashed.
+/// * `path` - The path to the file to be hashed.
 ///
 /// # Returns
 ///
 /// * `io::Result<String>` - On success, returns the hexadecimal string representation of the file's SHA-256 hash.
 ///   On failure, returns an `io::Error`.
-pub fn hash_file(path: &str) -> io::Result<String> {
-    // Open the file at the specified path
+pub fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    hash_file_with(path, &Sha256Hasher)
+}
+
+/// Computes the hash of the file at the given path with the given hasher.
+///
+/// # Arguments
+///
+/// * `path` - The path to the file to be hashed.
+/// * `hasher` - The hash algorithm to use.
+///
+/// # Returns
+///
+/// * `io::Result<String>` - On success, returns the hexadecimal digest of the file's contents.
+///   On failure, returns an `io::Error`.
+pub fn hash_file_with<P: AsRef<Path>>(path: P, hasher: &dyn ContentHasher) -> io::Result<String> {
     let mut file = File::open(path)?;
-    // Create a new SHA-256 hasher instance
-    let mut hasher = Sha256::new();
+    hasher.hash_reader(&mut file)
+}
+
+/// Computes a hash over a small sample of the file: its length, the first
+/// `sample_size` bytes and the last `sample_size` bytes.
+///
+/// Two files with different samples can never be identical, so this is used as a cheap
+/// pre-filter before hashing whole files. Equal samples do not imply equal contents.
+///
+/// # Arguments
+///
+/// * `path` - The path to the file to be sampled.
+/// * `sample_size` - Number of bytes to read from each end of the file.
+/// * `hasher` - The hash algorithm to use.
+///
+/// # Returns
+///
+/// * `io::Result<String>` - On success, returns the hexadecimal string representation of the sample hash.
+///   On failure, returns an `io::Error`.
+pub fn hash_file_sample<P: AsRef<Path>>(
+    path: P,
+    sample_size: u64,
+    hasher: &dyn ContentHasher,
+) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut sample = len.to_le_bytes().to_vec();
+
+    // Head of the file
+    (&mut file).take(sample_size).read_to_end(&mut sample)?;
+
+    // Tail of the file, unless the head already covered it
+    if len > sample_size {
+        let tail_start = len.saturating_sub(sample_size).max(sample_size);
+        file.seek(SeekFrom::Start(tail_start))?;
+        file.take(sample_size).read_to_end(&mut sample)?;
+    }
+
+    hasher.hash_reader(&mut sample.as_slice())
+}
+
+/// Reads `reader` to the end in fixed-size chunks, passing each chunk to `update`.
+fn read_chunks(reader: &mut dyn Read, mut update: impl FnMut(&[u8])) -> io::Result<()> {
     // Buffer to read file chunks
-    let mut buffer = [0u8; 1024];
+    let mut buffer = [0u8; 64 * 1024];
 
-    // Read the file in chunks and update the hasher
     loop {
-        let bytes_read = file.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        } // End of file reached
-        hasher.update(&buffer[..bytes_read]);
+        let bytes_read = match reader.read(&mut buffer) {
+            Ok(0) => break, // End of file reached
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        update(&buffer[..bytes_read]);
     }
 
-    // Finalize the hash and return it as a hexadecimal string
-    Ok(format!("{:x}", hasher.finalize()))
+    Ok(())
 }
@@ -0,0 +1,245 @@
+//! Append-only undo journal for destructive runs, and restoring from it.
+//!
+//! Every file that `delete_duplicates` deletes, trashes, links or deduplicates is
+//! recorded as one JSON object per line, written as soon as the action succeeds. A
+//! journal can later be replayed backwards with [`restore_journal`].
+
+use crate::{
+    fs_util::{absolute, replace_atomically, xdg_dir},
+    hashing::{HashAlgorithm, hash_file_with},
+    safe_delete::DuplicateAction,
+};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
+};
+
+/// One action taken on one duplicate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub action: DuplicateAction,
+    /// The duplicate's original path.
+    #[serde(with = "crate::path_encoding")]
+    pub path: PathBuf,
+    /// The copy that was kept (or linked to) in its place.
+    #[serde(with = "crate::path_encoding")]
+    pub kept: PathBuf,
+    /// Content hash shared by `path` and `kept`.
+    pub hash: String,
+    pub algorithm: HashAlgorithm,
+    /// Where the data went: the trashed file, or the file now sharing its data.
+    /// `None` when the file was deleted.
+    #[serde(with = "crate::path_encoding::option")]
+    pub destination: Option<PathBuf>,
+    /// The `.trashinfo` file written for a trashed file.
+    #[serde(with = "crate::path_encoding::option")]
+    pub trash_info: Option<PathBuf>,
+    /// Permission bits of the original file.
+    pub mode: Option<u32>,
+    /// Modification time of the original file.
+    pub modified: Option<SystemTime>,
+    /// When the action was taken, in RFC 3339 format.
+    pub timestamp: String,
+}
+
+/// An open journal that entries are appended to.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+/// Outcome of [`restore_journal`].
+#[derive(Debug, Default)]
+pub struct RestoreSummary {
+    /// Paths that were restored.
+    pub restored: Vec<PathBuf>,
+    /// Paths that could not be restored, with the reason.
+    pub unrestorable: Vec<(PathBuf, String)>,
+}
+
+impl Journal {
+    /// Creates a new journal at `path`, creating parent directories as needed.
+    /// Fails if the file already exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .append(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Journal { path, file })
+    }
+
+    /// Returns the journal's location.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `entry` and flushes it to disk.
+    pub fn record(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry).map_err(io::Error::other)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()
+    }
+}
+
+impl JournalEntry {
+    /// Starts an entry for `path`, capturing its current mode and mtime.
+    ///
+    /// Paths are made absolute so the journal can be restored from any directory.
+    /// Must be called before the action, while the original file still exists.
+    pub fn before_action(
+        action: DuplicateAction,
+        path: &Path,
+        kept: &Path,
+        hash: &str,
+        algorithm: HashAlgorithm,
+    ) -> Self {
+        let metadata = fs::metadata(path).ok();
+        JournalEntry {
+            action,
+            path: absolute(path),
+            kept: absolute(kept),
+            hash: hash.to_string(),
+            algorithm,
+            destination: None,
+            trash_info: None,
+            mode: metadata.as_ref().map(|m| m.mode() & 0o7777),
+            modified: metadata.and_then(|m| m.modified().ok()),
+            timestamp: Local::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Returns a fresh journal path under `$XDG_STATE_HOME/hashlaser/journals`,
+/// falling back to `$HOME/.local/state` when `XDG_STATE_HOME` is unset.
+pub fn default_journal_path() -> io::Result<PathBuf> {
+    let state_home = xdg_dir("XDG_STATE_HOME", ".local/state")?;
+    let name = format!(
+        "{}-{}.jsonl",
+        Local::now().format("%Y%m%d-%H%M%S"),
+        process::id()
+    );
+    Ok(state_home.join("hashlaser/journals").join(name))
+}
+
+/// Reads every entry of the journal at `path`, in the order they were written.
+pub fn read_journal<P: AsRef<Path>>(path: P) -> io::Result<Vec<JournalEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("journal line {}: {}", number + 1, e),
+            )
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Reverses the run recorded in the journal at `path` as far as possible.
+///
+/// Entries are undone newest first:
+///
+/// * trashed files are moved back and their `.trashinfo` is removed;
+/// * hard links are re-expanded into independent copies;
+/// * deleted files are recreated from the kept copy, provided it still has the recorded hash;
+/// * extent sharing needs no undo, since the files were never merged.
+///
+/// Restored copies get back their original permissions and mtime. Anything that can no
+/// longer be restored is listed in the summary rather than aborting the restore.
+pub fn restore_journal<P: AsRef<Path>>(path: P) -> io::Result<RestoreSummary> {
+    let mut summary = RestoreSummary::default();
+    for entry in read_journal(path)?.iter().rev() {
+        match restore_entry(entry) {
+            Ok(()) => summary.restored.push(entry.path.clone()),
+            Err(reason) => summary.unrestorable.push((entry.path.clone(), reason)),
+        }
+    }
+    Ok(summary)
+}
+
+/// Undoes a single journal entry.
+fn restore_entry(entry: &JournalEntry) -> Result<(), String> {
+    let path = entry.path.as_path();
+    match entry.action {
+        DuplicateAction::Trash => {
+            let source = entry
+                .destination
+                .as_deref()
+                .ok_or("journal does not record where the file was trashed")?;
+            if path.symlink_metadata().is_ok() {
+                return Err("original path is occupied".to_string());
+            }
+            fs::rename(source, path)
+                .map_err(|e| format!("cannot move {} back: {}", source.display(), e))?;
+            if let Some(info) = &entry.trash_info {
+                let _ = fs::remove_file(info);
+            }
+            Ok(())
+        }
+        DuplicateAction::Link => {
+            let linked = fs::metadata(path).map_err(|e| format!("cannot stat: {}", e))?;
+            let kept =
+                fs::metadata(&entry.kept).map_err(|e| format!("cannot stat kept copy: {}", e))?;
+            if (linked.dev(), linked.ino()) != (kept.dev(), kept.ino()) {
+                return Err(format!("no longer a hard link to {}", entry.kept.display()));
+            }
+            copy_independent(&entry.kept, path, entry)
+        }
+        DuplicateAction::Delete => {
+            if path.symlink_metadata().is_ok() {
+                return Err("original path is occupied".to_string());
+            }
+            match hash_file_with(&entry.kept, entry.algorithm.hasher()) {
+                Ok(hash) if hash == entry.hash => copy_independent(&entry.kept, path, entry),
+                Ok(_) => Err(format!(
+                    "deleted, and kept copy {} has changed",
+                    entry.kept.display()
+                )),
+                Err(e) => Err(format!(
+                    "deleted, and kept copy {} is unreadable: {}",
+                    entry.kept.display(),
+                    e
+                )),
+            }
+        }
+        DuplicateAction::DedupeExtents => Ok(()),
+    }
+}
+
+/// Copies `source` to a temporary name next to `dest` and renames it into place,
+/// restoring the mode and mtime recorded in `entry`.
+fn copy_independent(source: &Path, dest: &Path, entry: &JournalEntry) -> Result<(), String> {
+    replace_atomically(dest, "restore", |temp| {
+        fs::copy(source, temp)?;
+        // Set the mtime first: the mode may make the file read-only
+        if let Some(modified) = entry.modified {
+            File::options()
+                .write(true)
+                .open(temp)?
+                .set_modified(modified)?;
+        }
+        if let Some(mode) = entry.mode {
+            fs::set_permissions(temp, fs::Permissions::from_mode(mode))?;
+        }
+        Ok(())
+    })
+    .map_err(|e| format!("cannot copy {} back: {}", source.display(), e))
+}
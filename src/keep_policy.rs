@@ -0,0 +1,207 @@
+//! Choosing which file of a duplicate group survives.
+//!
+//! A [`KeepPolicy`] is an ordered list of [`KeepRule`]s. The first rule decides; later
+//! rules only break ties left by the earlier ones. Any tie left at the end is broken by
+//! lexicographic path order, so the choice never depends on scan order.
+
+use crate::scanner::FileEntry;
+use std::{
+    cmp::Ordering,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// A single criterion for picking the file to keep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeepRule {
+    /// Keep the file with the oldest modification time.
+    Oldest,
+    /// Keep the file with the newest modification time.
+    Newest,
+    /// Keep the file with the shortest path.
+    ShortestPath,
+    /// Keep the file with the longest path.
+    LongestPath,
+    /// Keep the lexicographically first path.
+    FirstPath,
+    /// Prefer files under the given directory.
+    Under(PathBuf),
+}
+
+/// An ordered list of [`KeepRule`]s, each breaking ties left by the previous ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeepPolicy {
+    rules: Vec<KeepRule>,
+}
+
+/// The file chosen by a [`KeepPolicy`] and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeepChoice {
+    /// Index of the kept file in the group.
+    pub index: usize,
+    /// Human-readable description of the rule that decided, e.g. `oldest modification time`.
+    pub reason: String,
+}
+
+impl KeepRule {
+    /// Orders `a` before `b` if this rule prefers keeping `a`.
+    fn compare(&self, a: &FileEntry, b: &FileEntry) -> Ordering {
+        let (a_path, b_path) = (a.path.as_path(), b.path.as_path());
+        match self {
+            // Unknown mtimes sort last either way
+            KeepRule::Oldest => match (a.modified, b.modified) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                (x, y) => x.is_none().cmp(&y.is_none()),
+            },
+            KeepRule::Newest => match (a.modified, b.modified) {
+                (Some(x), Some(y)) => y.cmp(&x),
+                (x, y) => x.is_none().cmp(&y.is_none()),
+            },
+            KeepRule::ShortestPath => a_path.as_os_str().len().cmp(&b_path.as_os_str().len()),
+            KeepRule::LongestPath => b_path.as_os_str().len().cmp(&a_path.as_os_str().len()),
+            KeepRule::FirstPath => a_path.as_os_str().cmp(b_path.as_os_str()),
+            KeepRule::Under(dir) => is_under(b_path, dir).cmp(&is_under(a_path, dir)),
+        }
+    }
+
+    /// Describes the rule as a reason for keeping a file.
+    fn reason(&self) -> String {
+        match self {
+            KeepRule::Oldest => "oldest modification time".to_string(),
+            KeepRule::Newest => "newest modification time".to_string(),
+            KeepRule::ShortestPath => "shortest path".to_string(),
+            KeepRule::LongestPath => "longest path".to_string(),
+            KeepRule::FirstPath => "first path in lexicographic order".to_string(),
+            KeepRule::Under(dir) => format!("under preferred directory {}", dir.display()),
+        }
+    }
+}
+
+impl KeepPolicy {
+    /// Creates a policy from rules in priority order.
+    pub fn new(rules: Vec<KeepRule>) -> Self {
+        KeepPolicy { rules }
+    }
+
+    /// Returns the rules in priority order.
+    pub fn rules(&self) -> &[KeepRule] {
+        &self.rules
+    }
+
+    /// Picks the file to keep from `files`, using the mtimes recorded by the scan.
+    ///
+    /// A followed symlink is only kept when every file is one: the link is not a copy of
+    /// its own, so keeping it would leave nothing under its name once the rest is gone.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `files` is empty.
+    pub fn choose(&self, files: &[FileEntry]) -> KeepChoice {
+        let links: Vec<bool> = files.iter().map(|file| file.path.is_symlink()).collect();
+        let mut order: Vec<usize> = (0..files.len()).collect();
+        order.sort_by(|&a, &b| {
+            links[a]
+                .cmp(&links[b])
+                .then_with(|| self.compare(&files[a], &files[b]))
+        });
+
+        let index = order[0];
+        // The reason is the first rule that separates the winner from the runner-up
+        let reason = match order.get(1) {
+            Some(&runner_up) if links[runner_up] && !links[index] => {
+                "the other copies are symbolic links".to_string()
+            }
+            Some(&runner_up) => self
+                .rules
+                .iter()
+                .chain([&KeepRule::FirstPath])
+                .find(|rule| rule.compare(&files[index], &files[runner_up]) == Ordering::Less)
+                .map(KeepRule::reason)
+                .unwrap_or_else(|| "only candidate".to_string()),
+            None => "only candidate".to_string(),
+        };
+
+        KeepChoice { index, reason }
+    }
+
+    /// Compares two paths by every rule in turn, then by path.
+    fn compare(&self, a: &FileEntry, b: &FileEntry) -> Ordering {
+        self.rules
+            .iter()
+            .map(|rule| rule.compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.path.as_os_str().cmp(b.path.as_os_str()))
+    }
+}
+
+/// Keeps the lexicographically first path.
+impl Default for KeepPolicy {
+    fn default() -> Self {
+        KeepPolicy::new(vec![KeepRule::FirstPath])
+    }
+}
+
+impl fmt::Display for KeepRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeepRule::Oldest => f.write_str("oldest"),
+            KeepRule::Newest => f.write_str("newest"),
+            KeepRule::ShortestPath => f.write_str("shortest"),
+            KeepRule::LongestPath => f.write_str("longest"),
+            KeepRule::FirstPath => f.write_str("first"),
+            KeepRule::Under(dir) => write!(f, "under:{}", dir.display()),
+        }
+    }
+}
+
+impl fmt::Display for KeepPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.rules.iter().map(KeepRule::to_string).collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+impl FromStr for KeepRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "oldest" => Ok(KeepRule::Oldest),
+            "newest" => Ok(KeepRule::Newest),
+            "shortest" => Ok(KeepRule::ShortestPath),
+            "longest" => Ok(KeepRule::LongestPath),
+            "first" => Ok(KeepRule::FirstPath),
+            other => match other.strip_prefix("under:") {
+                Some(dir) if !dir.is_empty() => Ok(KeepRule::Under(PathBuf::from(dir))),
+                _ => Err(format!(
+                    "unknown keep rule '{other}' (expected oldest, newest, shortest, longest, first or under:<dir>)"
+                )),
+            },
+        }
+    }
+}
+
+/// Parses a comma-separated list of rules, e.g. `under:/archive,oldest,shortest`.
+impl FromStr for KeepPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rules = s
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<KeepRule>, _>>()?;
+        Ok(KeepPolicy::new(rules))
+    }
+}
+
+/// Returns whether `path` lies under `dir`, comparing canonical paths when possible.
+fn is_under(path: &Path, dir: &Path) -> bool {
+    if path.starts_with(dir) {
+        return true;
+    }
+    match (fs::canonicalize(path), fs::canonicalize(dir)) {
+        (Ok(path), Ok(dir)) => path.starts_with(dir),
+        _ => false,
+    }
+}
@@ -5,17 +5,34 @@
 //!
 //! ## Modules
 //!
+//! - [`error`]: The crate's error type, carrying the path each failure occurred on.
 //! - [`hashing`]: Utilities for hashing files to identify duplicates efficiently.
+//! - [`hash_cache`]: Persistent cache of full-file hashes, keyed by device, inode, size and mtime.
 //! - [`file_compare`]: Functions for comparing files at a binary or content level.
 //! - [`scanner`]: Tools for scanning directories and collecting file metadata.
+//! - [`journal`]: Append-only undo journal for destructive runs, and restoring from it.
+//! - [`keep_policy`]: Policies for choosing which file of a duplicate group is kept.
+//! - [`path_encoding`]: Lossless JSON encoding of paths that are not valid UTF-8.
+//! - [`reflink`]: Copy-on-write extent sharing on btrfs/XFS via `FIDEDUPERANGE`.
 //! - [`report`]: Facilities for generating reports on duplicates and actions taken.
 //! - [`filter`]: Mechanisms for filtering files based on user-defined criteria.
 //! - [`safe_delete`]: Safe deletion utilities to remove duplicates without data loss.
+//! - [`stats`]: How much space duplicates waste, and where.
+//! - [`trash`]: Moving files to the freedesktop.org trash so deletions can be undone.
 //!
 //! Each module is documented individually with further details and usage examples.
+pub mod error;
 pub mod file_compare;
 pub mod filter;
+mod fs_util;
+pub mod hash_cache;
 pub mod hashing;
+pub mod journal;
+pub mod keep_policy;
+pub mod path_encoding;
+pub mod reflink;
 pub mod report;
 pub mod safe_delete;
 pub mod scanner;
+pub mod stats;
+pub mod trash;
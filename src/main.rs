@@ -1,55 +1,44 @@
-mod file_compare;
-mod filter;
+mod cli;
 mod handler;
-mod hashing;
-mod report;
-mod safe_delete;
-mod scanner;
 mod ui;
 
+use clap::{CommandFactory, Parser};
+use cli::{Cli, Command};
 use handler::*;
-use ui::{print_banner, print_error, print_info};
+use ui::{finish_output, print_banner, set_output_format};
 
-use std::{env, process};
+use std::{io, process};
 
 /// Entry point of the program.
-/// Parses command-line arguments and dispatches to the appropriate handler function.
+/// Parses command-line arguments, dispatches to the appropriate handler function and
+/// exits with the [`handler::Status`] it returns.
 fn main() {
-    // Display ASCII banner at launch
-    print_banner();
-
-    // Collect command-line arguments
-    let args: Vec<String> = env::args().collect();
+    // Invalid arguments, --help and --version exit here, before anything is printed
+    let cli = Cli::parse();
 
-    // Require at least one command
-    if args.len() < 2 {
-        print_error("Error: Not enough arguments.\n");
-        print_usage();
-        process::exit(1);
+    // Completion scripts go to stdout untouched
+    if let Command::Completions { shell } = cli.command {
+        clap_complete::generate(shell, &mut Cli::command(), "hashlaser", &mut io::stdout());
+        return;
     }
 
-    // Match and dispatch commands
-    match args[1].as_str() {
-        "compare" => handle_compare_command(&args),
-        "scan" => handle_scan_command(&args),
-        "report" => handle_report_command(&args),
-        "delete" => handle_delete_command(&args),
-        "filter" => handle_filter_command(&args),
-        _ => {
-            print_error(&format!("Error: Unknown command '{}'", args[1]));
-            print_usage();
-            process::exit(1);
-        }
-    }
-}
+    // Display ASCII banner at launch, unless the output is for machines
+    set_output_format(cli.format);
+    print_banner();
 
-/// Prints usage instructions for all commands.
-fn print_usage() {
-    print_info("📘 Usage Guide:\n");
-    println!("  hashlaser compare <file1> <file2>        🔍 Compare two files");
-    println!("  hashlaser scan <dir> [options]           🧪 Scan directory for duplicates");
-    println!("      Options: --min <bytes> --max <bytes> --ext txt,csv --regex <pattern>");
-    println!("  hashlaser report <dir> <output.json>     📄 Generate JSON report");
-    println!("  hashlaser delete <dir> [--dry-run]       🗑️ Delete duplicate files");
-    println!("  hashlaser filter <dir> [options]         🎯 Scan with filtering");
+    // Dispatch to the command's handler
+    let status = match &cli.command {
+        Command::Compare(args) => handle_compare_command(args),
+        Command::Scan(options) => handle_scan_command(options),
+        Command::Report(args) => handle_report_command(args),
+        Command::Delete(args) => handle_delete_command(args),
+        Command::Apply(args) => handle_apply_command(args),
+        Command::Filter(options) => handle_filter_command(options),
+        Command::Stats(args) => handle_stats_command(args),
+        Command::Restore { journal } => handle_restore_command(journal),
+        Command::Cache(command) => handle_cache_command(command),
+        Command::Completions { .. } => unreachable!("handled above"),
+    };
+    finish_output();
+    process::exit(status as i32);
 }
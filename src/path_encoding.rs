@@ -0,0 +1,147 @@
+//! Lossless JSON encoding of paths.
+//!
+//! JSON strings must be valid Unicode, but Unix paths are arbitrary bytes. Paths that
+//! are valid UTF-8 are written as plain strings. Any other path is written as an object,
+//! `{"bytes": "caf\\xe9.txt"}`, whose string holds the path with every byte that is not
+//! part of valid UTF-8 written as `\xNN` and every backslash doubled, so it decodes back
+//! to exactly the original bytes.
+//!
+//! Use with `#[serde(with = "crate::path_encoding")]` on `PathBuf` and `&Path` fields, or the
+//! [`option`] and [`vec`] submodules for `Option<PathBuf>` and `Vec<PathBuf>`.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use std::{
+    ffi::OsString,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+};
+
+/// The JSON form of one path.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Encoded {
+    Utf8(String),
+    Bytes { bytes: String },
+}
+
+impl Encoded {
+    fn new(path: &Path) -> Self {
+        match path.to_str() {
+            Some(utf8) => Encoded::Utf8(utf8.to_string()),
+            None => Encoded::Bytes {
+                bytes: escape(path.as_os_str().as_bytes()),
+            },
+        }
+    }
+
+    fn into_path(self) -> Result<PathBuf, String> {
+        match self {
+            Encoded::Utf8(utf8) => Ok(PathBuf::from(utf8)),
+            Encoded::Bytes { bytes } => Ok(PathBuf::from(OsString::from_vec(unescape(&bytes)?))),
+        }
+    }
+}
+
+/// Serializes a path losslessly.
+pub fn serialize<P, S>(path: &P, serializer: S) -> Result<S::Ok, S::Error>
+where
+    P: AsRef<Path> + ?Sized,
+    S: Serializer,
+{
+    Encoded::new(path.as_ref()).serialize(serializer)
+}
+
+/// Deserializes a path written by [`serialize`], or any plain string.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    Encoded::deserialize(deserializer)?
+        .into_path()
+        .map_err(de::Error::custom)
+}
+
+/// Encoding for `Option<PathBuf>`.
+pub mod option {
+    use super::Encoded;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+    use std::path::PathBuf;
+
+    pub fn serialize<S: Serializer>(
+        path: &Option<PathBuf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        path.as_deref().map(Encoded::new).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<PathBuf>, D::Error> {
+        Option::<Encoded>::deserialize(deserializer)?
+            .map(Encoded::into_path)
+            .transpose()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Encoding for `Vec<PathBuf>`.
+pub mod vec {
+    use super::Encoded;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+    use std::path::PathBuf;
+
+    pub fn serialize<S: Serializer>(paths: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded: Vec<Encoded> = paths.iter().map(|path| Encoded::new(path)).collect();
+        encoded.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<PathBuf>, D::Error> {
+        Vec::<Encoded>::deserialize(deserializer)?
+            .into_iter()
+            .map(Encoded::into_path)
+            .collect::<Result<_, _>>()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Writes `bytes` as UTF-8 text, escaping invalid bytes as `\xNN` and `\` as `\\`.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        escaped.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    escaped
+}
+
+/// Reverses [`escape`].
+fn unescape(escaped: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 2)
+                    .ok_or_else(|| format!("invalid byte escape '\\x{hex}' in path"))?;
+                bytes.push(byte);
+            }
+            other => {
+                return Err(format!(
+                    "invalid escape '\\{}' in path",
+                    other.map(String::from).unwrap_or_default()
+                ));
+            }
+        }
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,142 @@
+//! Copy-on-write extent sharing for btrfs, XFS and other reflink-capable filesystems.
+//!
+//! Deduplication here goes through the `FIDEDUPERANGE` ioctl rather than `FICLONE`:
+//! the kernel locks both files and compares the ranges itself before sharing any
+//! extents, so a file that changes underneath us is never clobbered. Both files stay
+//! independent; writing to one later simply un-shares the affected extents.
+
+use std::{io, path::Path};
+
+/// Largest range submitted in one request. Btrfs caps a single dedupe at 16 MiB.
+#[cfg(target_os = "linux")]
+const MAX_DEDUPE_CHUNK: u64 = 16 * 1024 * 1024;
+
+/// Makes `dest` share the extents of `src`, which must have identical contents.
+///
+/// # Returns
+///
+/// * `io::Result<u64>` - The number of bytes now shared. Fails with
+///   `io::ErrorKind::Unsupported` when the filesystem cannot share extents (e.g. ext4 or tmpfs),
+///   with `io::ErrorKind::InvalidData` when the kernel finds the contents differ, and with
+///   an error of another kind when the kernel stops before sharing the whole file.
+#[cfg(target_os = "linux")]
+pub fn dedupe_file_extents<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dest: Q) -> io::Result<u64> {
+    use std::{fs::File, os::fd::AsRawFd};
+
+    let src = File::open(src)?;
+    // The destination must be writable (or owned by us) for the kernel to accept it
+    let dest = File::options().read(true).write(true).open(dest)?;
+    let len = src.metadata()?.len();
+
+    let mut offset = 0;
+    while offset < len {
+        let mut request = DedupeRequest {
+            range: FileDedupeRange {
+                src_offset: offset,
+                src_length: (len - offset).min(MAX_DEDUPE_CHUNK),
+                dest_count: 1,
+                reserved1: 0,
+                reserved2: 0,
+            },
+            info: FileDedupeRangeInfo {
+                dest_fd: i64::from(dest.as_raw_fd()),
+                dest_offset: offset,
+                bytes_deduped: 0,
+                status: 0,
+                reserved: 0,
+            },
+        };
+
+        let ret = unsafe { libc::ioctl(src.as_raw_fd(), FIDEDUPERANGE, &mut request) };
+        if ret < 0 {
+            return Err(classify(io::Error::last_os_error()));
+        }
+        match request.info.status {
+            FILE_DEDUPE_RANGE_SAME => {}
+            FILE_DEDUPE_RANGE_DIFFERS => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "kernel reports the file contents differ",
+                ));
+            }
+            status if status < 0 => return Err(classify(io::Error::from_raw_os_error(-status))),
+            status => {
+                return Err(io::Error::other(format!(
+                    "unexpected dedupe status {status}"
+                )));
+            }
+        }
+        if request.info.bytes_deduped == 0 {
+            // Reporting success for a partly shared file would overstate what was freed
+            return Err(io::Error::other(format!(
+                "kernel stopped sharing extents after {offset} of {len} bytes"
+            )));
+        }
+        offset += request.info.bytes_deduped;
+    }
+
+    Ok(offset)
+}
+
+/// Extent sharing needs Linux ioctls; other platforms always report it as unsupported.
+#[cfg(not(target_os = "linux"))]
+pub fn dedupe_file_extents<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dest: Q) -> io::Result<u64> {
+    let _ = (src.as_ref(), dest.as_ref());
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extent sharing is only available on Linux",
+    ))
+}
+
+/// Turns the errors a filesystem without dedupe support returns into `Unsupported`.
+#[cfg(target_os = "linux")]
+fn classify(err: io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL) => io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("filesystem does not support extent sharing ({err})"),
+        ),
+        Some(libc::EXDEV) => io::Error::new(
+            io::ErrorKind::CrossesDevices,
+            "files are on different filesystems",
+        ),
+        _ => err,
+    }
+}
+
+#[cfg(target_os = "linux")]
+const FILE_DEDUPE_RANGE_SAME: i32 = 0;
+#[cfg(target_os = "linux")]
+const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+#[cfg(target_os = "linux")]
+const FIDEDUPERANGE: libc::Ioctl = libc::_IOWR::<FileDedupeRange>(0x94, 54);
+
+/// `struct file_dedupe_range` from `<linux/fs.h>`, without its trailing `info` array.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct FileDedupeRange {
+    src_offset: u64,
+    src_length: u64,
+    dest_count: u16,
+    reserved1: u16,
+    reserved2: u32,
+}
+
+/// `struct file_dedupe_range_info` from `<linux/fs.h>`.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct FileDedupeRangeInfo {
+    dest_fd: i64,
+    dest_offset: u64,
+    bytes_deduped: u64,
+    status: i32,
+    reserved: u32,
+}
+
+/// A dedupe request with a single destination, laid out as the kernel expects.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct DedupeRequest {
+    range: FileDedupeRange,
+    info: FileDedupeRangeInfo,
+}
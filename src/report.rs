@@ -1,53 +1,589 @@
-use serde::Serialize;
-use std::{collections::HashMap, fs::File, io::Write, path::Path};
+//! JSON reports of duplicate groups, which double as reviewable deletion plans.
+//!
+//! A report lists, for every group, the file that is kept and the action to take on
+//! each other file, together with the size and mtime of every file when the report was
+//! written. It can be edited by hand and then executed with
+//! [`crate::safe_delete::apply_plan`], which refuses any file that changed in the meantime.
+//!
+//! The same groups can also be written for people rather than for `apply`: as CSV with one
+//! row per file, as Markdown for pasting into tickets, or as a self-contained HTML page.
+//! See [`ReportFormat`]. Only JSON encodes every path losslessly; the other formats show
+//! paths that are not valid UTF-8 with replacement characters.
 
-#[derive(Serialize)]
-struct DuplicateGroup {
-    hash: String,
-    files: Vec<String>,
+use crate::{
+    filter::FilterSettings,
+    hashing::HashAlgorithm,
+    keep_policy::KeepPolicy,
+    safe_delete::DuplicateAction,
+    scanner::{DuplicateGroup, FileEntry, FileSnapshot, ScanResult},
+    stats::{DEFAULT_TOP, ScanStats, human_size},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+/// The file format of a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// A [`Plan`] that can be edited and applied.
+    #[default]
+    Json,
+    /// One row per file: group, digest, size, path, mtime and action.
+    Csv,
+    /// A summary and a table per group.
+    Markdown,
+    /// A self-contained page with sortable groups and a per-directory breakdown.
+    Html,
 }
 
-#[derive(Serialize)]
-struct Report {
-    duplicates: Vec<DuplicateGroup>,
+impl ReportFormat {
+    /// Guesses the format from the extension of `path`: `.json`, `.csv`, `.md` or
+    /// `.markdown`, `.html` or `.htm`.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(ReportFormat::Json),
+            "csv" => Some(ReportFormat::Csv),
+            "md" | "markdown" => Some(ReportFormat::Markdown),
+            "html" | "htm" => Some(ReportFormat::Html),
+            _ => None,
+        }
+    }
 }
 
-pub fn write_json_report<P: AsRef<Path>>(
-    hash_map: &HashMap<String, Vec<String>>,
-    output_path: P,
-) -> std::io::Result<()> {
-    // A vector of `DuplicateGroup` structs, where each group represents a set of files
-    // that share the same hash (i.e., are duplicates).
-    //
-    // This vector is constructed by filtering the entries of a hash map to include only
-    // those where multiple files share the same hash, and then mapping each such entry
-    // to a `DuplicateGroup` containing the hash and the list of duplicate files.
-    //
-    // Example:
-    // // Given a hash_map of file hashes to file paths:
-    // let duplicates: Vec<DuplicateGroup> = hash_map
-    //     .iter()
-    //     .filter(|(_, files)| files.len() > 1)
-    //     .map(|(hash, files)| DuplicateGroup {
-    //         hash: hash.clone(),
-    //         files: files.clone(),
-    //     })
-    //     .collect();
-    // // `duplicates` now contains groups of files with identical content.
-    let duplicates: Vec<DuplicateGroup> = hash_map
-        .iter()
-        .filter(|(_, files)| files.len() > 1)
-        .map(|(hash, files)| DuplicateGroup {
-            hash: hash.clone(),
-            files: files.clone(),
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Markdown => "markdown",
+            ReportFormat::Html => "html",
         })
-        .collect();
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            other => Err(format!(
+                "unknown report format '{other}' (expected json, csv, markdown or html)"
+            )),
+        }
+    }
+}
+
+/// Version of the report format written by this crate. Reports without a version
+/// predate versioning and read as 0.
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// A report: every duplicate group and what to do with it.
+///
+/// Groups are sorted by reclaimable bytes, largest first, then by hash, and the files of
+/// each group by path, so reports of an unchanged tree differ only in `generated_at`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    /// Version of the report format; see [`REPORT_SCHEMA_VERSION`].
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Version of the tool that wrote the report.
+    #[serde(default)]
+    pub tool_version: String,
+    /// When the report was written, in RFC 3339 and UTC. Set `SOURCE_DATE_EPOCH` to
+    /// pin it, e.g. for golden-file tests.
+    #[serde(default)]
+    pub generated_at: String,
+    /// The directories scanned.
+    #[serde(default, with = "crate::path_encoding::vec")]
+    pub roots: Vec<PathBuf>,
+    /// The filters the scan applied.
+    #[serde(default)]
+    pub filters: FilterSettings,
+    /// The algorithm that produced the group hashes.
+    pub algorithm: HashAlgorithm,
+    /// The keep policy the plan was generated with, for reference only.
+    pub keep_policy: String,
+    /// How much the scan covered and how much space the duplicates waste, for
+    /// reference only.
+    #[serde(default)]
+    pub summary: ScanStats,
+    pub duplicates: Vec<PlanGroup>,
+}
+
+/// One duplicate group of a [`Plan`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanGroup {
+    pub hash: String,
+    /// Every file of the group, as found by the scan.
+    #[serde(with = "crate::path_encoding::vec")]
+    pub files: Vec<PathBuf>,
+    /// The file that survives.
+    #[serde(with = "crate::path_encoding")]
+    pub keep: PathBuf,
+    pub keep_reason: String,
+    /// What happens to the other files. Files without an entry are left alone.
+    pub actions: Vec<PlannedAction>,
+    /// Size and mtime of every file when the plan was written.
+    pub snapshots: Vec<RecordedSnapshot>,
+}
+
+/// An action on one duplicate, taken against the group's kept file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedAction {
+    #[serde(with = "crate::path_encoding")]
+    pub path: PathBuf,
+    pub action: DuplicateAction,
+}
+
+/// The size and mtime of one file when the plan was written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedSnapshot {
+    #[serde(with = "crate::path_encoding")]
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub snapshot: FileSnapshot,
+}
+
+impl Plan {
+    /// Builds a plan that keeps the file chosen by `keep` in every group of `scan` and
+    /// applies `action` to the rest.
+    pub fn from_scan(scan: &ScanResult, keep: &KeepPolicy, action: DuplicateAction) -> Self {
+        // The keep policy picks the survivor of each group
+        let duplicates = sorted_groups(scan)
+            .iter()
+            .map(|group| {
+                let choice = keep.choose(&group.files);
+                let kept = group.files[choice.index].inode();
+                // Other names of the kept inode free nothing and must survive with it, and
+                // followed symlinks are no copies of their own
+                let actions = group
+                    .files
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, file)| {
+                        i != choice.index && file.inode() != kept && !file.path.is_symlink()
+                    })
+                    .map(|(_, file)| PlannedAction {
+                        path: file.path.clone(),
+                        action,
+                    })
+                    .collect();
+                let snapshots = group
+                    .files
+                    .iter()
+                    .map(|file| RecordedSnapshot {
+                        path: file.path.clone(),
+                        snapshot: file.snapshot(),
+                    })
+                    .collect();
+                PlanGroup {
+                    hash: group.digest.clone(),
+                    files: group.paths().map(Path::to_path_buf).collect(),
+                    keep: group.files[choice.index].path.clone(),
+                    keep_reason: choice.reason,
+                    actions,
+                    snapshots,
+                }
+            })
+            .collect();
 
-    let report = Report { duplicates };
-    let json = serde_json::to_string_pretty(&report).expect("Serialization failed");
+        Plan {
+            schema_version: REPORT_SCHEMA_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            generated_at: timestamp(),
+            roots: scan.roots.clone(),
+            filters: scan.filters.clone(),
+            algorithm: scan.algorithm,
+            keep_policy: keep.to_string(),
+            summary: ScanStats::from_scan(scan, keep, DEFAULT_TOP),
+            duplicates,
+        }
+    }
+}
+
+/// The groups of `scan` in report order: most reclaimable bytes first, then by hash,
+/// with the files of each group sorted by path.
+fn sorted_groups(scan: &ScanResult) -> Vec<DuplicateGroup> {
+    let mut groups = scan.groups.clone();
+    for group in &mut groups {
+        group.files.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    groups.sort_by(|a, b| {
+        b.reclaimable_bytes()
+            .cmp(&a.reclaimable_bytes())
+            .then_with(|| a.digest.cmp(&b.digest))
+    });
+    groups
+}
+
+/// The current time for [`Plan::generated_at`], or `SOURCE_DATE_EPOCH` if it is set.
+fn timestamp() -> String {
+    let pinned = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0));
+    pinned
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Writes the duplicate groups of `scan` to `output_path` as pretty-printed JSON.
+///
+/// The hash algorithm is recorded at the top of the report, so digests produced by
+/// different algorithms are never mistaken for one another. Each group also records
+/// the file `keep` would preserve and why, and `action` for every other file, so the
+/// report can be reviewed and then applied as a [`Plan`].
+pub fn write_json_report<P: AsRef<Path>>(
+    scan: &ScanResult,
+    keep: &KeepPolicy,
+    action: DuplicateAction,
+    output_path: P,
+) -> io::Result<()> {
+    let plan = Plan::from_scan(scan, keep, action);
+    let json = serde_json::to_string_pretty(&plan).expect("Serialization failed");
 
     let mut file = File::create(output_path)?;
     file.write_all(json.as_bytes())?;
 
     Ok(())
 }
+
+/// Writes the duplicate groups of `scan` to `output_path` in `format`.
+///
+/// Every format lists the same groups, the file `keep` would preserve and `action` for
+/// every other file. Only [`ReportFormat::Json`] can be read back with [`read_plan`].
+pub fn write_report<P: AsRef<Path>>(
+    scan: &ScanResult,
+    keep: &KeepPolicy,
+    action: DuplicateAction,
+    format: ReportFormat,
+    output_path: P,
+) -> io::Result<()> {
+    if format == ReportFormat::Json {
+        return write_json_report(scan, keep, action, output_path);
+    }
+
+    let plan = Plan::from_scan(scan, keep, action);
+    let mut out = BufWriter::new(File::create(output_path)?);
+    match format {
+        ReportFormat::Json => unreachable!("written above"),
+        ReportFormat::Csv => write_csv(scan, &plan, &mut out)?,
+        ReportFormat::Markdown => write_markdown(scan, &plan, &mut out)?,
+        ReportFormat::Html => write_html(scan, &plan, &mut out)?,
+    }
+    out.flush()
+}
+
+/// Reads a plan previously written by [`write_json_report`], possibly edited since.
+/// Fails if it was written with a newer [`REPORT_SCHEMA_VERSION`].
+pub fn read_plan<P: AsRef<Path>>(path: P) -> io::Result<Plan> {
+    let json = fs::read_to_string(path)?;
+    let plan: Plan =
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if plan.schema_version > REPORT_SCHEMA_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "report schema version {} is newer than the supported version {REPORT_SCHEMA_VERSION}",
+                plan.schema_version
+            ),
+        ));
+    }
+    Ok(plan)
+}
+
+/// What happens to `file` under `planned`: `keep`, the name of its action, or `none`
+/// for a file the plan leaves alone.
+fn role(planned: &PlanGroup, file: &FileEntry) -> &'static str {
+    if file.path == planned.keep {
+        return "keep";
+    }
+    planned
+        .actions
+        .iter()
+        .find(|action| action.path == file.path)
+        .map_or("none", |action| action.action.name())
+}
+
+/// Formats an mtime as RFC 3339 in UTC, or an empty string if it is unknown.
+fn format_mtime(modified: Option<SystemTime>) -> String {
+    modified
+        .map(|time| DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// Writes one CSV row per file of every group.
+fn write_csv(scan: &ScanResult, plan: &Plan, out: &mut impl Write) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(["group", "hash", "size", "path", "modified", "action"])?;
+    let groups = sorted_groups(scan);
+    for (index, (group, planned)) in groups.iter().zip(&plan.duplicates).enumerate() {
+        for file in &group.files {
+            writer.write_record([
+                (index + 1).to_string(),
+                group.digest.clone(),
+                group.size.to_string(),
+                file.path.to_string_lossy().into_owned(),
+                format_mtime(file.modified),
+                role(planned, file).to_string(),
+            ])?;
+        }
+    }
+    writer.flush()
+}
+
+/// Escapes the characters Markdown would interpret in table cells and text.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Writes a summary followed by a table per group.
+fn write_markdown(scan: &ScanResult, plan: &Plan, out: &mut impl Write) -> io::Result<()> {
+    let summary = &plan.summary;
+    writeln!(out, "# Duplicate report\n")?;
+    writeln!(
+        out,
+        "- **Generated:** {} by hashlaser {}",
+        plan.generated_at, plan.tool_version
+    )?;
+    let roots: Vec<String> = plan
+        .roots
+        .iter()
+        .map(|root| escape_markdown(&root.to_string_lossy()))
+        .collect();
+    writeln!(out, "- **Roots:** {}", roots.join(", "))?;
+    writeln!(out, "- **Algorithm:** {}", plan.algorithm)?;
+    writeln!(
+        out,
+        "- **Keep policy:** {}",
+        escape_markdown(&plan.keep_policy)
+    )?;
+    writeln!(
+        out,
+        "- **Scanned:** {} files, {}",
+        summary.files_scanned,
+        human_size(summary.bytes_scanned)
+    )?;
+    writeln!(
+        out,
+        "- **Groups:** {} ({} redundant files)",
+        summary.groups, summary.redundant_files
+    )?;
+    writeln!(
+        out,
+        "- **Reclaimable:** {} ({} bytes)",
+        human_size(summary.reclaimable_bytes),
+        summary.reclaimable_bytes
+    )?;
+
+    if !summary.directories.is_empty() {
+        writeln!(out, "\n## Directories with the most redundant bytes\n")?;
+        writeln!(out, "| Directory | Redundant files | Redundant bytes |")?;
+        writeln!(out, "|-----------|-----------------|-----------------|")?;
+        for directory in &summary.directories {
+            writeln!(
+                out,
+                "| {} | {} | {} |",
+                escape_markdown(&directory.path.to_string_lossy()),
+                directory.redundant_files,
+                human_size(directory.redundant_bytes)
+            )?;
+        }
+    }
+
+    let groups = sorted_groups(scan);
+    for (index, (group, planned)) in groups.iter().zip(&plan.duplicates).enumerate() {
+        writeln!(
+            out,
+            "\n## Group {}: {} files of {}, {} reclaimable\n",
+            index + 1,
+            group.files.len(),
+            human_size(group.size),
+            human_size(group.reclaimable_bytes())
+        )?;
+        writeln!(out, "Hash: `{}`\n", group.digest)?;
+        writeln!(out, "| Action | Path | Modified |")?;
+        writeln!(out, "|--------|------|----------|")?;
+        for file in &group.files {
+            writeln!(
+                out,
+                "| {} | {} | {} |",
+                role(planned, file),
+                escape_markdown(&file.path.to_string_lossy()),
+                format_mtime(file.modified)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Escapes text for HTML element content and attribute values.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Styles and sorting script of the HTML report, inlined so the page is self-contained.
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Duplicate report</title>
+<style>
+body { font-family: system-ui, sans-serif; margin: 2rem; color: #222; }
+table { border-collapse: collapse; margin: 0.5rem 0 1rem; }
+th, td { border: 1px solid #ccc; padding: 0.25rem 0.5rem; text-align: left; }
+td.num { text-align: right; }
+code { font-size: 0.9em; }
+details { margin: 0.25rem 0; }
+summary { cursor: pointer; }
+.keep { color: #17702b; font-weight: bold; }
+#sort button { margin-right: 0.25rem; }
+</style>
+</head>
+<body>
+"#;
+
+const HTML_TAIL: &str = r#"<script>
+document.querySelectorAll('#sort button').forEach(function (button) {
+  button.addEventListener('click', function () {
+    var key = button.dataset.key;
+    var groups = document.getElementById('groups');
+    Array.from(groups.children)
+      .sort(function (a, b) {
+        // Hashes ascending, sizes and counts largest first
+        if (key === 'hash') {
+          return a.dataset.hash.localeCompare(b.dataset.hash);
+        }
+        return Number(b.dataset[key]) - Number(a.dataset[key]);
+      })
+      .forEach(function (group) { groups.appendChild(group); });
+  });
+});
+</script>
+</body>
+</html>
+"#;
+
+/// Writes a self-contained HTML page: totals, a per-directory breakdown and the groups,
+/// which can be sorted by reclaimable space, size, number of files or hash.
+fn write_html(scan: &ScanResult, plan: &Plan, out: &mut impl Write) -> io::Result<()> {
+    out.write_all(HTML_HEAD.as_bytes())?;
+    let summary = &plan.summary;
+    writeln!(out, "<h1>Duplicate report</h1>")?;
+    let roots: Vec<String> = plan
+        .roots
+        .iter()
+        .map(|root| format!("<code>{}</code>", escape_html(&root.to_string_lossy())))
+        .collect();
+    writeln!(
+        out,
+        "<p>Generated {} by hashlaser {} from {}.</p>",
+        escape_html(&plan.generated_at),
+        escape_html(&plan.tool_version),
+        roots.join(", ")
+    )?;
+    writeln!(
+        out,
+        "<p>Scanned {} files ({}). {} group(s) with {} redundant files, <strong>{}</strong> ({} bytes) reclaimable. Algorithm {}, keep policy <code>{}</code>.</p>",
+        summary.files_scanned,
+        human_size(summary.bytes_scanned),
+        summary.groups,
+        summary.redundant_files,
+        human_size(summary.reclaimable_bytes),
+        summary.reclaimable_bytes,
+        plan.algorithm,
+        escape_html(&plan.keep_policy)
+    )?;
+
+    writeln!(out, "<h2>Directories with the most redundant bytes</h2>")?;
+    writeln!(
+        out,
+        "<table>\n<tr><th>Directory</th><th>Redundant files</th><th>Redundant bytes</th></tr>"
+    )?;
+    for directory in &summary.directories {
+        writeln!(
+            out,
+            "<tr><td><code>{}</code></td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+            escape_html(&directory.path.to_string_lossy()),
+            directory.redundant_files,
+            human_size(directory.redundant_bytes)
+        )?;
+    }
+    writeln!(out, "</table>")?;
+
+    writeln!(out, "<h2>Groups</h2>")?;
+    writeln!(
+        out,
+        "<p id=\"sort\">Sort by: <button data-key=\"reclaimable\">Reclaimable</button><button data-key=\"size\">Size</button><button data-key=\"files\">Files</button><button data-key=\"hash\">Hash</button></p>"
+    )?;
+    writeln!(out, "<div id=\"groups\">")?;
+    let groups = sorted_groups(scan);
+    for (group, planned) in groups.iter().zip(&plan.duplicates) {
+        writeln!(
+            out,
+            "<details data-hash=\"{}\" data-size=\"{}\" data-files=\"{}\" data-reclaimable=\"{}\">",
+            escape_html(&group.digest),
+            group.size,
+            group.files.len(),
+            group.reclaimable_bytes()
+        )?;
+        writeln!(
+            out,
+            "<summary>{} files of {}, {} reclaimable <code>{}</code></summary>",
+            group.files.len(),
+            human_size(group.size),
+            human_size(group.reclaimable_bytes()),
+            escape_html(&group.digest)
+        )?;
+        writeln!(
+            out,
+            "<table>\n<tr><th>Action</th><th>Path</th><th>Modified</th></tr>"
+        )?;
+        for file in &group.files {
+            let role = role(planned, file);
+            writeln!(
+                out,
+                "<tr><td class=\"{role}\">{role}</td><td><code>{}</code></td><td>{}</td></tr>",
+                escape_html(&file.path.to_string_lossy()),
+                format_mtime(file.modified)
+            )?;
+        }
+        writeln!(out, "</table>\n</details>")?;
+    }
+    writeln!(out, "</div>")?;
+    out.write_all(HTML_TAIL.as_bytes())
+}
@@ -1,54 +1,793 @@
-use std::{collections::HashMap, fs, io};
+use crate::{
+    error::Error,
+    file_compare::files_identical,
+    fs_util::replace_atomically,
+    hashing::HashAlgorithm,
+    journal::{Journal, JournalEntry},
+    keep_policy::{KeepChoice, KeepPolicy},
+    reflink::dedupe_file_extents,
+    report::Plan,
+    scanner::{FileEntry, FileSnapshot, ScanResult},
+    trash::{Trash, TrashedFile},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-/// Deletes duplicate files from the provided hash map, keeping only one copy per group.
+/// What to do with each duplicate that is not kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateAction {
+    /// Unlink the file. Not recoverable.
+    #[default]
+    Delete,
+    /// Move the file to the freedesktop.org trash (see [`crate::trash`]).
+    Trash,
+    /// Atomically replace the file with a hard link to the kept copy, so the path
+    /// stays valid while the space is reclaimed. The path takes on the kept copy's
+    /// permissions and ownership.
+    Link,
+    /// Share the kept copy's extents on a copy-on-write filesystem (see [`crate::reflink`]).
+    /// Files stay independent and keep their own metadata.
+    DedupeExtents,
+}
+
+impl DuplicateAction {
+    /// Returns the action's name, e.g. `dedupe-extents`, as written in reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            DuplicateAction::Delete => "delete",
+            DuplicateAction::Trash => "trash",
+            DuplicateAction::Link => "link",
+            DuplicateAction::DedupeExtents => "dedupe-extents",
+        }
+    }
+
+    /// Verb used in progress messages, e.g. "Would link".
+    fn verb(self) -> &'static str {
+        match self {
+            DuplicateAction::Delete => "delete",
+            DuplicateAction::Trash => "trash",
+            DuplicateAction::Link => "link",
+            DuplicateAction::DedupeExtents => "share extents of",
+        }
+    }
+}
+
+/// Options controlling how [`delete_duplicates`] disposes of duplicates.
+#[derive(Debug, Clone, Default)]
+pub struct DeleteOptions {
+    /// Only print what would be done.
+    pub dry_run: bool,
+    pub action: DuplicateAction,
+    /// Decides which file of each group survives.
+    pub keep: KeepPolicy,
+    /// Home trash directory to use instead of `$XDG_DATA_HOME/Trash`.
+    pub trash_home: Option<PathBuf>,
+    /// Undo journal to create and record every action in. Ignored on dry runs.
+    pub journal: Option<PathBuf>,
+    /// Directories whose files are never deleted or modified. A group with a file under
+    /// one of them keeps such a file, and only the copies elsewhere are acted on.
+    pub reference: Vec<PathBuf>,
+    /// Receives every step of the run. Without one, each step is printed as text,
+    /// failures and skipped files to stderr.
+    pub on_event: Option<DeleteEventHandler>,
+}
+
+/// A step of [`delete_duplicates`] or [`apply_plan`]. Serializes to an object whose
+/// `type` field names the variant, e.g. `{"type": "deleted", "path": "a.txt"}`.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum DeleteEvent<'a> {
+    /// A duplicate group is about to be processed, keeping `keep`.
+    Keep {
+        hash: &'a str,
+        #[serde(with = "crate::path_encoding")]
+        keep: &'a Path,
+        reason: &'a str,
+    },
+    /// A group is left alone because every copy is under a reference directory.
+    GroupLeftAlone { hash: &'a str },
+    /// A file under a reference directory is kept alongside the group's kept file.
+    Protected {
+        #[serde(with = "crate::path_encoding")]
+        path: &'a Path,
+    },
+    /// The group's kept file changed or could not be checked, so its `count` other files
+    /// are skipped.
+    GroupSkipped { count: usize, reason: &'a str },
+    /// `path` cannot be hard linked to `keep` across filesystems, so it is kept as its
+    /// filesystem's copy.
+    KeptOnOtherFilesystem {
+        #[serde(with = "crate::path_encoding")]
+        path: &'a Path,
+        #[serde(with = "crate::path_encoding")]
+        keep: &'a Path,
+    },
+    /// Dry run: `action` would be applied to `path`.
+    WouldAct {
+        action: DuplicateAction,
+        #[serde(with = "crate::path_encoding")]
+        path: &'a Path,
+    },
+    Deleted {
+        #[serde(with = "crate::path_encoding")]
+        path: &'a Path,
+    },
+    Trashed {
+        #[serde(with = "crate::path_encoding")]
+        path: &'a Path,
+        #[serde(with = "crate::path_encoding")]
+        destination: &'a Path,
+    },
+    Linked {
+        #[serde(with = "crate::path_encoding")]
+        path: &'a Path,
+        #[serde(with = "crate::path_encoding")]
+        target: &'a Path,
+    },
+    SharedExtents {
+        #[serde(with = "crate::path_encoding")]
+        path: &'a Path,
+        #[serde(with = "crate::path_encoding")]
+        target: &'a Path,
+        bytes: u64,
+    },
+    /// `path` is left alone because acting on it would be unsafe or is not supported.
+    Skipped {
+        #[serde(with = "crate::path_encoding")]
+        path: &'a Path,
+        reason: &'a str,
+    },
+    /// `path` could not be checked or acted on.
+    Failed {
+        #[serde(with = "crate::path_encoding")]
+        path: &'a Path,
+        error: &'a str,
+    },
+}
+
+impl DeleteEvent<'_> {
+    /// Whether the event reports a file or group that was left alone, which is printed
+    /// to stderr.
+    fn is_diagnostic(&self) -> bool {
+        matches!(
+            self,
+            DeleteEvent::GroupSkipped { .. }
+                | DeleteEvent::Skipped { .. }
+                | DeleteEvent::Failed { .. }
+        )
+    }
+}
+
+impl fmt::Display for DeleteEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DeleteEvent::Keep { hash, keep, reason } => write!(
+                f,
+                "\n🧬 Duplicate group (Hash: {hash})\n📂 Keeping: {} ({reason})",
+                keep.display()
+            ),
+            DeleteEvent::GroupLeftAlone { hash } => write!(
+                f,
+                "\n🧬 Duplicate group (Hash: {hash})\n🛡️ Leaving group alone: every copy is under a reference directory"
+            ),
+            DeleteEvent::Protected { path } => write!(f, "🛡️ Protected: {}", path.display()),
+            DeleteEvent::GroupSkipped { reason, .. } => {
+                write!(f, "⚠️ Skipping group: kept file {reason}")
+            }
+            DeleteEvent::KeptOnOtherFilesystem { path, keep } => write!(
+                f,
+                "⚠️ Falling back: {} is on a different filesystem than {}, keeping it as that filesystem's copy",
+                path.display(),
+                keep.display()
+            ),
+            DeleteEvent::WouldAct { action, path } => {
+                write!(f, "🧪 Would {}: {}", action.verb(), path.display())
+            }
+            DeleteEvent::Deleted { path } => write!(f, "🗑️ Deleted: {}", path.display()),
+            DeleteEvent::Trashed { path, destination } => write!(
+                f,
+                "♻️ Trashed: {} -> {}",
+                path.display(),
+                destination.display()
+            ),
+            DeleteEvent::Linked { path, target } => {
+                write!(f, "🔗 Linked: {} -> {}", path.display(), target.display())
+            }
+            DeleteEvent::SharedExtents {
+                path,
+                target,
+                bytes,
+            } => write!(
+                f,
+                "🧩 Shared extents: {} ({bytes} bytes) with {}",
+                path.display(),
+                target.display()
+            ),
+            DeleteEvent::Skipped { path, reason } => {
+                write!(f, "⚠️ Skipping {}: {reason}", path.display())
+            }
+            DeleteEvent::Failed { path, error } => {
+                write!(f, "❌ Skipping {}: {error}", path.display())
+            }
+        }
+    }
+}
+
+/// A callback receiving the [`DeleteEvent`]s of a run, set as [`DeleteOptions::on_event`].
+#[derive(Clone)]
+pub struct DeleteEventHandler(Arc<dyn Fn(&DeleteEvent<'_>) + Send + Sync>);
+
+impl DeleteEventHandler {
+    pub fn new(handler: impl Fn(&DeleteEvent<'_>) + Send + Sync + 'static) -> Self {
+        DeleteEventHandler(Arc::new(handler))
+    }
+}
+
+impl fmt::Debug for DeleteEventHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DeleteEventHandler")
+    }
+}
+
+/// What a run of [`delete_duplicates`] or [`apply_plan`] did.
+#[derive(Debug, Default)]
+pub struct DeleteSummary {
+    /// Files acted on, or that would have been on a dry run.
+    pub processed: usize,
+    /// Files left alone because they changed since the scan, no longer match the kept
+    /// copy, or sit on a filesystem that cannot take the action.
+    pub skipped: usize,
+    /// Of the skipped files, those on a filesystem that does not support the action.
+    pub unsupported: usize,
+    /// Files that could not be checked or acted on.
+    pub errors: Vec<Error>,
+}
+
+/// Why a file was left alone.
+enum Refusal {
+    /// Acting on the file would be unsafe, e.g. because it changed since the scan.
+    Skip(String),
+    /// The file could not be checked.
+    Failed(Error),
+}
+
+/// What happened to a duplicate's data.
+enum Disposal {
+    Deleted,
+    Trashed(TrashedFile),
+    Linked,
+    Shared(u64),
+}
+
+/// Deletes duplicate files from a scan result, keeping only one copy per group.
+///
+/// The copy that survives is chosen by [`DeleteOptions::keep`].
+///
+/// Nothing is removed on the strength of the hash alone. Before each deletion the
+/// kept file and the file to delete are checked against the size and mtime recorded
+/// by the scan, and then compared byte by byte. A file that changed since the scan, or
+/// whose contents differ from the kept copy, is skipped and reported.
+///
+/// Files under a [`DeleteOptions::reference`] directory are never touched. When a group
+/// has any, the keep policy picks among them, and groups made up only of reference files
+/// are left alone.
 ///
 /// # Arguments
 ///
-/// * `duplicates` - A reference to a HashMap where each key is a hash string and the value is a vector of file paths with that hash.
-/// * `dry_run` - If true, the function will only print which files would be deleted, but will not actually delete any files.
+/// * `scan` - The result of a scan, holding the duplicate groups and the metadata recorded for each file.
+/// * `options` - Whether this is a dry run, and whether duplicates are unlinked, trashed,
+///   hard linked or deduplicated at the extent level.
+///
+/// Hard links cannot span filesystems. When a group does, the first copy found on each
+/// other filesystem is kept as that filesystem's copy and the rest are linked to it.
 ///
 /// # Returns
 ///
-/// * `io::Result<()>` - Returns Ok(()) if all deletions succeed or if in dry run mode. Returns an error if any file deletion fails.
+/// * `io::Result<DeleteSummary>` - How many files were processed and skipped, and the
+///   per-file errors. Returns an error, before touching any file, if the trash directory
+///   cannot be determined, a reference directory cannot be resolved or the journal cannot
+///   be created, and stops if a journal entry cannot be written.
 ///
 /// # Example
 ///
 /// ```text
-/// // let mut duplicates = HashMap::new();
-/// // duplicates.insert("hash1".to_string(), vec!["file1.txt".to_string(), "file2.txt".to_string()]);
-/// // delete_duplicates(&duplicates, true)?;
+/// // let scan = scan_directory_with_stats("assets", &filters, HashAlgorithm::Sha256);
+/// // delete_duplicates(&scan, &DeleteOptions { dry_run: true, ..Default::default() })?;
 /// ```
-pub fn delete_duplicates(
-    duplicates: &HashMap<String, Vec<String>>,
-    dry_run: bool,
-) -> io::Result<()> {
+pub fn delete_duplicates(scan: &ScanResult, options: &DeleteOptions) -> io::Result<DeleteSummary> {
+    let mut run = Run::start(
+        options,
+        scan.algorithm,
+        options.action == DuplicateAction::Trash,
+    )?;
+
     // Iterate over each group of duplicate files
-    for (hash, files) in duplicates {
-        // Skip groups with only one file (no duplicates)
-        if files.len() <= 1 {
+    for group in &scan.groups {
+        // Reference files all survive; the policy picks the kept one among them
+        let protected: Vec<bool> = group
+            .files
+            .iter()
+            .map(|file| run.reference_dir(&file.path).is_some())
+            .collect();
+        if protected.iter().all(|&p| p) {
+            run.emit(&DeleteEvent::GroupLeftAlone {
+                hash: &group.digest,
+            });
             continue;
         }
+        let choice = if protected.contains(&true) {
+            let candidates: Vec<usize> = (0..group.files.len()).filter(|&i| protected[i]).collect();
+            let files: Vec<FileEntry> =
+                candidates.iter().map(|&i| group.files[i].clone()).collect();
+            let choice = options.keep.choose(&files);
+            KeepChoice {
+                index: candidates[choice.index],
+                reason: match files.len() {
+                    1 => "under a reference directory".to_string(),
+                    _ => format!("under a reference directory, {}", choice.reason),
+                },
+            }
+        } else {
+            options.keep.choose(&group.files)
+        };
 
-        // Keep the first file, delete the rest
-        let (keep, delete) = files.split_first().unwrap();
+        // Keep the file chosen by the policy, delete the rest
+        let kept = &group.files[choice.index];
+        let keep = kept.path.as_path();
+        let delete: Vec<&FileEntry> = group
+            .files
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != choice.index && !protected[i])
+            .map(|(_, file)| file)
+            .collect();
+
+        run.emit(&DeleteEvent::Keep {
+            hash: &group.digest,
+            keep,
+            reason: &choice.reason,
+        });
+        for (file, _) in group.files.iter().zip(&protected).filter(|&(_, &p)| p) {
+            if file.path != keep {
+                run.emit(&DeleteEvent::Protected { path: &file.path });
+            }
+        }
 
-        println!("\n🧬 Duplicate group (Hash: {})", hash);
-        println!("📂 Keeping: {}", keep);
+        // Deleting against a kept copy that changed since the scan could lose data
+        if let Err(refusal) = check_unchanged(keep, Some(&kept.snapshot())) {
+            // Reference files were never going to be touched, so only the rest count
+            run.refuse_group(delete.len(), refusal);
+            continue;
+        }
+
+        // The copy each filesystem's duplicates are linked to, keyed by device
+        let mut link_targets: HashMap<u64, &Path> = HashMap::new();
+        if options.action == DuplicateAction::Link
+            && let Ok(metadata) = fs::metadata(keep)
+        {
+            link_targets.insert(metadata.dev(), keep);
+        }
 
         // Iterate over files to delete
-        for path in delete {
-            if dry_run {
-                // In dry run mode, just print what would be deleted
-                println!("🧪 Would delete: {}", path);
+        for file in delete {
+            let path = file.path.as_path();
+            // Another name of the kept inode frees nothing and takes the kept data with it
+            if file.inode() == kept.inode() || shares_inode(keep, path) {
+                let reason = format!("already a hard link to {}", keep.display());
+                run.refuse(path, Refusal::Skip(reason));
+                continue;
+            }
+            // A followed symlink was compared through its target; the link is no copy
+            if path.is_symlink() {
+                let reason = format!("{} is a symlink", path.display());
+                run.refuse(path, Refusal::Skip(reason));
+                continue;
+            }
+            let target = if options.action == DuplicateAction::Link {
+                match link_target(path, &mut link_targets) {
+                    Ok(Some(target)) => target,
+                    Ok(None) => {
+                        run.emit(&DeleteEvent::KeptOnOtherFilesystem { path, keep });
+                        run.summary.skipped += 1;
+                        continue;
+                    }
+                    Err(refusal) => {
+                        run.refuse(path, refusal);
+                        continue;
+                    }
+                }
             } else {
-                // Attempt to delete the file and print the result
-                match fs::remove_file(path) {
-                    Ok(_) => println!("🗑️ Deleted: {}", path),
-                    Err(e) => eprintln!("❌ Failed to delete {}: {}", path, e),
+                keep
+            };
+
+            run.act(
+                options.action,
+                target,
+                path,
+                &group.digest,
+                Some(&file.snapshot()),
+            )?;
+        }
+    }
+
+    Ok(run.summary)
+}
+
+/// Executes a plan read from a JSON report, exactly as written.
+///
+/// For each group, the kept file and every file with an action must still match the
+/// size and mtime recorded in the plan, and each file must still be byte-for-byte
+/// identical to the kept file. Anything else is refused and reported. Only the plan's
+/// actions are taken; [`DeleteOptions::action`] and [`DeleteOptions::keep`] are ignored.
+/// Actions on files under a [`DeleteOptions::reference`] directory are refused too.
+///
+/// Links always point at the group's kept file, so a link across filesystems is skipped.
+///
+/// # Returns
+///
+/// * `io::Result<DeleteSummary>` - As for [`delete_duplicates`]: fails, before touching any
+///   file, if the trash directory cannot be determined or the journal cannot be created, and
+///   stops if a journal entry cannot be written.
+pub fn apply_plan(plan: &Plan, options: &DeleteOptions) -> io::Result<DeleteSummary> {
+    let needs_trash = plan
+        .duplicates
+        .iter()
+        .flat_map(|group| &group.actions)
+        .any(|planned| planned.action == DuplicateAction::Trash);
+    let mut run = Run::start(options, plan.algorithm, needs_trash)?;
+
+    for group in &plan.duplicates {
+        run.emit(&DeleteEvent::Keep {
+            hash: &group.hash,
+            keep: &group.keep,
+            reason: &group.keep_reason,
+        });
+
+        let snapshots: HashMap<PathBuf, FileSnapshot> = group
+            .snapshots
+            .iter()
+            .map(|recorded| (recorded.path.clone(), recorded.snapshot))
+            .collect();
+        if let Err(refusal) = check_unchanged(&group.keep, snapshots.get(&group.keep)) {
+            run.refuse_group(group.actions.len(), refusal);
+            continue;
+        }
+
+        for planned in &group.actions {
+            if planned.path == group.keep {
+                let reason = "it is the group's kept file".to_string();
+                run.refuse(&planned.path, Refusal::Skip(reason));
+                continue;
+            }
+            if shares_inode(&group.keep, &planned.path) {
+                let reason = format!("already a hard link to {}", group.keep.display());
+                run.refuse(&planned.path, Refusal::Skip(reason));
+                continue;
+            }
+            run.act(
+                planned.action,
+                &group.keep,
+                &planned.path,
+                &group.hash,
+                snapshots.get(&planned.path),
+            )?;
+        }
+    }
+
+    Ok(run.summary)
+}
+
+/// The trash, journal and running summary shared by every action of one run.
+struct Run<'a> {
+    options: &'a DeleteOptions,
+    algorithm: HashAlgorithm,
+    trash: Option<Trash>,
+    journal: Option<Journal>,
+    /// Each reference directory as given and, when it differs, canonicalized.
+    references: Vec<PathBuf>,
+    summary: DeleteSummary,
+}
+
+impl<'a> Run<'a> {
+    /// Locates the trash if `needs_trash`, and creates the journal unless this is a dry run.
+    fn start(
+        options: &'a DeleteOptions,
+        algorithm: HashAlgorithm,
+        needs_trash: bool,
+    ) -> io::Result<Self> {
+        let trash = match (needs_trash, &options.trash_home) {
+            (true, Some(home)) => Some(Trash::with_home(home)),
+            (true, None) => Some(Trash::from_env()?),
+            _ => None,
+        };
+        let mut references = Vec::new();
+        for dir in &options.reference {
+            let canonical =
+                fs::canonicalize(dir).map_err(|e| io::Error::from(Error::io(dir, e)))?;
+            references.push(dir.clone());
+            if canonical != *dir {
+                references.push(canonical);
+            }
+        }
+        let journal = match &options.journal {
+            Some(path) if !options.dry_run => Some(Journal::create(path)?),
+            _ => None,
+        };
+        Ok(Run {
+            options,
+            algorithm,
+            trash,
+            journal,
+            references,
+            summary: DeleteSummary::default(),
+        })
+    }
+
+    /// Returns the reference directory `path` lies under, if any.
+    fn reference_dir(&self, path: &Path) -> Option<&Path> {
+        if self.references.is_empty() {
+            return None;
+        }
+        let canonical = fs::canonicalize(path).ok();
+        self.references
+            .iter()
+            .find(|dir| {
+                path.starts_with(dir) || canonical.as_ref().is_some_and(|c| c.starts_with(dir))
+            })
+            .map(PathBuf::as_path)
+    }
+
+    /// Hands `event` to the caller's handler, or prints it.
+    fn emit(&self, event: &DeleteEvent<'_>) {
+        match &self.options.on_event {
+            Some(handler) => (handler.0)(event),
+            None if event.is_diagnostic() => eprintln!("{event}"),
+            None => println!("{event}"),
+        }
+    }
+
+    /// Reports and counts a file that is left alone.
+    fn refuse(&mut self, path: &Path, refusal: Refusal) {
+        match refusal {
+            Refusal::Skip(reason) => {
+                self.emit(&DeleteEvent::Skipped {
+                    path,
+                    reason: &reason,
+                });
+                self.summary.skipped += 1;
+            }
+            Refusal::Failed(e) => {
+                self.emit(&DeleteEvent::Failed {
+                    path,
+                    error: &e.to_string(),
+                });
+                self.summary.errors.push(e);
+            }
+        }
+    }
+
+    /// Reports a group whose kept file cannot be relied on; the `count` files it would
+    /// have acted on are skipped.
+    fn refuse_group(&mut self, count: usize, refusal: Refusal) {
+        self.summary.skipped += count;
+        match refusal {
+            Refusal::Skip(reason) => self.emit(&DeleteEvent::GroupSkipped {
+                count,
+                reason: &reason,
+            }),
+            Refusal::Failed(e) => {
+                self.emit(&DeleteEvent::GroupSkipped {
+                    count,
+                    reason: &e.to_string(),
+                });
+                self.summary.errors.push(e);
+            }
+        }
+    }
+
+    /// Verifies that `path` is still a copy of `target` and applies `action` to it,
+    /// reporting the outcome.
+    ///
+    /// Failures to act on the file are reported and skipped; only a failure to write
+    /// the journal is returned.
+    fn act(
+        &mut self,
+        action: DuplicateAction,
+        target: &Path,
+        path: &Path,
+        hash: &str,
+        recorded: Option<&FileSnapshot>,
+    ) -> io::Result<()> {
+        if let Some(dir) = self.reference_dir(path) {
+            let reason = format!("it is under reference directory {}", dir.display());
+            self.refuse(path, Refusal::Skip(reason));
+            return Ok(());
+        }
+        if let Err(refusal) = verify_duplicate(target, path, recorded) {
+            self.refuse(path, refusal);
+            return Ok(());
+        }
+
+        if self.options.dry_run {
+            // In dry run mode, just report what would be done
+            self.emit(&DeleteEvent::WouldAct { action, path });
+            self.summary.processed += 1;
+            return Ok(());
+        }
+
+        let mut entry = JournalEntry::before_action(action, path, target, hash, self.algorithm);
+        match dispose(action, target, path, self.trash.as_ref()) {
+            Ok(disposal) => {
+                match disposal {
+                    Disposal::Deleted => self.emit(&DeleteEvent::Deleted { path }),
+                    Disposal::Trashed(trashed) => {
+                        self.emit(&DeleteEvent::Trashed {
+                            path,
+                            destination: &trashed.files_path,
+                        });
+                        entry.destination = Some(trashed.files_path.clone());
+                        entry.trash_info = Some(trashed.info_path.clone());
+                    }
+                    Disposal::Linked => {
+                        self.emit(&DeleteEvent::Linked { path, target });
+                        entry.destination = Some(target.to_path_buf());
+                    }
+                    Disposal::Shared(bytes) => {
+                        self.emit(&DeleteEvent::SharedExtents {
+                            path,
+                            target,
+                            bytes,
+                        });
+                        entry.destination = Some(target.to_path_buf());
+                    }
                 }
+                self.summary.processed += 1;
+                if let Some(journal) = &mut self.journal {
+                    journal.record(&entry)?;
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::CrossesDevices | io::ErrorKind::Unsupported
+                ) =>
+            {
+                self.emit(&DeleteEvent::Skipped {
+                    path,
+                    reason: &e.to_string(),
+                });
+                self.summary.skipped += 1;
+                if e.kind() == io::ErrorKind::Unsupported {
+                    self.summary.unsupported += 1;
+                }
+            }
+            Err(e) => {
+                let error = Error::io(path, e);
+                self.emit(&DeleteEvent::Failed {
+                    path,
+                    error: &error.to_string(),
+                });
+                self.summary.errors.push(error);
             }
         }
+        Ok(())
+    }
+}
+
+/// Applies `action` to the duplicate `path`, whose contents match `target`.
+fn dispose(
+    action: DuplicateAction,
+    target: &Path,
+    path: &Path,
+    trash: Option<&Trash>,
+) -> io::Result<Disposal> {
+    match (action, trash) {
+        // Move the file to the trash so it can be restored later
+        (DuplicateAction::Trash, Some(trash)) => trash.trash_file(path).map(Disposal::Trashed),
+        // Never fall back to a permanent delete when the trash was asked for
+        (DuplicateAction::Trash, None) => Err(io::Error::other(
+            "no trash directory was located for this run",
+        )),
+        // Swap the file for a hard link to the target
+        (DuplicateAction::Link, _) => replace_with_link(target, path).map(|_| Disposal::Linked),
+        // Let the kernel share extents between the two identical files
+        (DuplicateAction::DedupeExtents, _) => {
+            dedupe_file_extents(target, path).map(Disposal::Shared)
+        }
+        // Delete the file outright
+        (DuplicateAction::Delete, _) => fs::remove_file(path).map(|_| Disposal::Deleted),
+    }
+}
+
+/// Picks the file `path` should be hard linked to: the group's copy on the same device.
+///
+/// Returns `None`, and records `path` as that device's copy, if the group has no file on
+/// its device yet. Files that already share an inode with the target are skipped.
+fn link_target<'a>(
+    path: &'a Path,
+    link_targets: &mut HashMap<u64, &'a Path>,
+) -> Result<Option<&'a Path>, Refusal> {
+    let metadata = fs::metadata(path).map_err(|e| Refusal::Failed(Error::io(path, e)))?;
+    let Some(&target) = link_targets.get(&metadata.dev()) else {
+        link_targets.insert(metadata.dev(), path);
+        return Ok(None);
+    };
+
+    match fs::metadata(target) {
+        Ok(target_metadata) if target_metadata.ino() == metadata.ino() => Err(Refusal::Skip(
+            format!("already a hard link to {}", target.display()),
+        )),
+        Ok(_) => Ok(Some(target)),
+        Err(e) => Err(Refusal::Failed(Error::io(target, e))),
+    }
+}
+
+/// Whether `a` and `b` are names of the same inode, so acting on one removes data the
+/// other still needs.
+fn shares_inode(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+/// Atomically replaces `path` with a hard link to `target`.
+///
+/// The link is created under a temporary name next to `path` and then renamed over it,
+/// so `path` always refers to one of the two identical copies.
+fn replace_with_link(target: &Path, path: &Path) -> io::Result<()> {
+    replace_atomically(path, "link", |temp| fs::hard_link(target, temp))
+}
+
+/// Checks that `path` is still an exact copy of `keep` and unchanged since the scan.
+fn verify_duplicate(
+    keep: &Path,
+    path: &Path,
+    recorded: Option<&FileSnapshot>,
+) -> Result<(), Refusal> {
+    check_unchanged(path, recorded)?;
+    match files_identical(keep, path) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Refusal::Skip(format!(
+            "contents differ from {}",
+            keep.display()
+        ))),
+        Err(e) => Err(Refusal::Failed(Error::io(path, e))),
+    }
+}
+
+/// Checks that `path` is a regular file whose size and mtime still match `recorded`,
+/// taken by the scan.
+///
+/// A symlink is refused: it is not a copy of its target, and removing the target
+/// through it could destroy the only real copy.
+fn check_unchanged(path: &Path, recorded: Option<&FileSnapshot>) -> Result<(), Refusal> {
+    let recorded = recorded
+        .ok_or_else(|| Refusal::Skip(format!("{} was not recorded by the scan", path.display())))?;
+    let metadata = fs::symlink_metadata(path).map_err(|e| Refusal::Failed(Error::io(path, e)))?;
+    if metadata.file_type().is_symlink() {
+        return Err(Refusal::Skip(format!("{} is a symlink", path.display())));
     }
+    let current = FileSnapshot::capture(path).map_err(|e| Refusal::Failed(Error::io(path, e)))?;
 
+    if current.size != recorded.size {
+        return Err(Refusal::Skip(format!(
+            "{} changed size since the scan",
+            path.display()
+        )));
+    }
+    if current.modified != recorded.modified {
+        return Err(Refusal::Skip(format!(
+            "{} was modified since the scan",
+            path.display()
+        )));
+    }
     Ok(())
 }
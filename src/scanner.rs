@@ -1,16 +1,259 @@
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    hash::Hash,
+    io,
+    os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::SystemTime,
 };
 
-use crate::filter::FilterOptions;
-use crate::hashing::hash_file;
+use crate::error::Error;
+use crate::filter::{FilterOptions, FilterSettings};
+use crate::hash_cache::HashCache;
+use crate::hashing::{HashAlgorithm, hash_file_sample, hash_file_with};
+use ignore::{
+    Match,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+
+/// Number of bytes read from each end of a file during the sample stage.
+pub const SAMPLE_SIZE: u64 = 4096;
+
+/// Statistics for a single stage of the duplicate detection pipeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageStats {
+    /// Name of the stage: `size`, `sample` or `full`.
+    pub name: &'static str,
+    /// Number of candidate files entering the stage. Hard links to one inode count once.
+    pub candidates: usize,
+    /// Number of candidates ruled out by the stage.
+    pub removed: usize,
+    /// Whether every candidate went through the stage. A scan cancelled during the stage
+    /// leaves it incomplete, and `removed` then only counts candidates ruled out for
+    /// certain: a file whose possible copies were never read is not counted.
+    pub complete: bool,
+}
+
+/// How the directory walk treats symbolic links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Ignore symlinks.
+    #[default]
+    Skip,
+    /// Follow symlinks to files and directories. Each is resolved to its real path, so a
+    /// link is never treated as a copy of its target and no directory is walked twice.
+    Follow,
+    /// Ignore symlinks, but list them in [`ScanResult::symlinks`].
+    Report,
+}
+
+impl fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SymlinkPolicy::Skip => "skip",
+            SymlinkPolicy::Follow => "follow",
+            SymlinkPolicy::Report => "report",
+        })
+    }
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "skip" => Ok(SymlinkPolicy::Skip),
+            "follow" => Ok(SymlinkPolicy::Follow),
+            "report" => Ok(SymlinkPolicy::Report),
+            other => Err(format!(
+                "unknown symlink policy '{other}' (expected skip, follow or report)"
+            )),
+        }
+    }
+}
+
+/// Size and modification time of a file, as recorded when it was scanned.
+///
+/// Destructive actions compare a fresh snapshot against the recorded one and skip
+/// any file that changed after the scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileSnapshot {
+    /// Takes a snapshot of the file at `path`.
+    pub fn capture<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(FileSnapshot::from(&fs::metadata(path)?))
+    }
+}
+
+impl From<&fs::Metadata> for FileSnapshot {
+    fn from(metadata: &fs::Metadata) -> Self {
+        FileSnapshot {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+/// A file found by a scan, with the metadata recorded before it was hashed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// Device the file lives on.
+    pub dev: u64,
+    /// Inode number on `dev`.
+    pub ino: u64,
+    /// Permission bits and file type, as in `st_mode`.
+    pub mode: u32,
+    /// Index of the scan root the file was found under, in [`ScanResult::roots`].
+    pub root: usize,
+}
+
+impl FileEntry {
+    /// Records the metadata of the file at `path`.
+    pub fn capture<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let metadata = fs::metadata(&path)?;
+        Ok(FileEntry::new(path.as_ref().to_path_buf(), &metadata))
+    }
+
+    /// Creates an entry for `path` from metadata already read.
+    pub fn new(path: PathBuf, metadata: &fs::Metadata) -> Self {
+        FileEntry {
+            path,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            mode: metadata.mode(),
+            root: 0,
+        }
+    }
+
+    /// The `(dev, ino)` pair identifying the file's data. Hard links share it.
+    pub fn inode(&self) -> (u64, u64) {
+        (self.dev, self.ino)
+    }
+
+    /// The size and mtime destructive actions check before touching the file.
+    pub fn snapshot(&self) -> FileSnapshot {
+        FileSnapshot {
+            size: self.size,
+            modified: self.modified,
+        }
+    }
+}
+
+/// Files with identical contents.
+///
+/// A group always spans at least two inodes. Hard links to any of them are listed as
+/// separate files, since they are separate names, but their data is stored only once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Hash of the shared contents, produced by [`ScanResult::algorithm`].
+    pub digest: String,
+    /// Size of each file in bytes.
+    pub size: u64,
+    /// The files, in path order.
+    pub files: Vec<FileEntry>,
+}
+
+impl DuplicateGroup {
+    /// Iterates over the paths of the group's files.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|file| file.path.as_path())
+    }
+
+    /// Number of distinct inodes, i.e. copies of the data actually stored.
+    pub fn inodes(&self) -> usize {
+        let mut inodes: Vec<_> = self.files.iter().map(FileEntry::inode).collect();
+        inodes.sort_unstable();
+        inodes.dedup();
+        inodes.len()
+    }
+
+    /// Whether `file` is a hard link to another file of the group.
+    pub fn is_linked(&self, file: &FileEntry) -> bool {
+        self.files
+            .iter()
+            .any(|other| other.path != file.path && other.inode() == file.inode())
+    }
+
+    /// Whether the group has files under more than one scan root.
+    pub fn spans_roots(&self) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.root != self.files[0].root)
+    }
+
+    /// Bytes freed by keeping a single copy. Each inode is counted once, so removing
+    /// a name whose inode has other names frees nothing. Followed symlinks are left out:
+    /// no action ever removes the file behind one.
+    pub fn reclaimable_bytes(&self) -> u64 {
+        let mut inodes: Vec<_> = self
+            .files
+            .iter()
+            .filter(|file| !file.path.is_symlink())
+            .map(FileEntry::inode)
+            .collect();
+        inodes.sort_unstable();
+        inodes.dedup();
+        self.size * (inodes.len() as u64).saturating_sub(1)
+    }
+}
+
+/// Outcome of a staged duplicate scan.
+#[derive(Debug, Default)]
+pub struct ScanResult {
+    /// The directories scanned, in the order given.
+    pub roots: Vec<PathBuf>,
+    /// Groups of duplicate files, ordered by digest.
+    pub groups: Vec<DuplicateGroup>,
+    /// The algorithm that produced the digests in `groups`.
+    pub algorithm: HashAlgorithm,
+    /// The filters the scan applied.
+    pub filters: FilterSettings,
+    /// Symlinks found while walking, if [`SymlinkPolicy::Report`] was used.
+    pub symlinks: Vec<PathBuf>,
+    /// Per-stage statistics, in pipeline order.
+    pub stages: Vec<StageStats>,
+    /// Number of files that passed the filters and were considered.
+    pub files_scanned: usize,
+    /// Total size of those files. Hard links to one inode count once.
+    pub bytes_scanned: u64,
+    /// Paths that could not be listed, read or hashed. They are missing from `groups`.
+    pub errors: Vec<Error>,
+    /// Whether the scan was cancelled before it finished. `groups` then holds only the
+    /// groups confirmed until then, each complete.
+    pub cancelled: bool,
+}
+
+impl ScanResult {
+    /// Keeps only the groups with files under more than one root, e.g. the files of a
+    /// backup that already exist elsewhere.
+    pub fn retain_cross_root(&mut self) {
+        self.groups.retain(DuplicateGroup::spans_roots);
+    }
+}
 
 /// Scans a directory recursively and finds duplicate files based on SHA-256 hash.
 ///
+/// Use [`scan_directory_with_stats`] to pick a different [`HashAlgorithm`].
+///
 /// # Arguments
 ///
 /// * `dir` - The root directory to scan for files.
@@ -18,74 +261,716 @@ use crate::hashing::hash_file;
 ///
 /// # Returns
 ///
-/// A `HashMap` where the key is the SHA-256 hash of the file contents,
-/// and the value is a vector of file paths (as strings) that have that hash.
+/// The [`DuplicateGroup`]s, each holding the files that share one SHA-256 hash.
+/// Files without a duplicate are not included. Files that could not be read are
+/// left out silently; [`scan_directory_with_stats`] reports them.
 ///
 /// # Example
 ///
 /// ```text
 /// // let duplicates = scan_directory_for_duplicates("/some/path", &filter_options);
 /// ```
-pub fn scan_directory_for_duplicates(
-    dir: &str,
+pub fn scan_directory_for_duplicates<P: AsRef<Path>>(
+    dir: P,
     filters: &FilterOptions,
-) -> HashMap<String, Vec<String>> {
-    // Collect all files recursively from the directory
-    let files = collect_files_recursively(Path::new(dir));
-
-    // Filter files based on the provided filter options
-    let filtered_files: Vec<PathBuf> = files
-        .into_iter()
-        .filter(|path| filters.matches(path))
-        .collect();
-
-    // Shared, thread-safe map to store hashes and their corresponding file paths
-    let hash_map: Arc<Mutex<HashMap<String, Vec<String>>>> = Arc::new(Mutex::new(HashMap::new()));
-
-    // Compute hashes in parallel and populate the map
-    filtered_files.par_iter().for_each(|file_path| {
-        if let Ok(hash) = hash_file(file_path.to_str().unwrap()) {
-            let mut map = hash_map.lock().unwrap();
-            map.entry(hash)
-                .or_default()
-                .push(file_path.to_string_lossy().to_string());
-        }
-    });
-
-    // Unwrap the Arc and Mutex to return the final HashMap
-    Arc::try_unwrap(hash_map).unwrap().into_inner().unwrap()
+) -> Vec<DuplicateGroup> {
+    scan_directory_with_stats(dir, filters, HashAlgorithm::default()).groups
 }
 
-/// Recursively collects all file paths under the given directory.
+/// Scans a directory recursively for duplicates using a staged pipeline.
+///
+/// Files are first grouped by exact size, then by a hash of a small head/tail sample
+/// (see [`hash_file_sample`]), and only the files that survive both stages are fully
+/// hashed. Every stage drops candidates that no longer share a group with another file.
+///
+/// Hard links are recognised by their shared `(dev, ino)` and go through the stages as
+/// one candidate, so each inode is read once. Names of one inode with no other copy
+/// form no group.
 ///
 /// # Arguments
 ///
-/// * `dir` - The directory path to start scanning from.
+/// * `dir` - The root directory to scan for files.
+/// * `filters` - Filter options to apply to each file.
+/// * `algorithm` - Hash algorithm used for the sample and full-hash stages.
 ///
 /// # Returns
 ///
-/// A vector of `PathBuf` objects representing all files found under the directory.
+/// A [`ScanResult`] with the duplicate groups and how many candidates each stage removed.
+pub fn scan_directory_with_stats<P: AsRef<Path>>(
+    dir: P,
+    filters: &FilterOptions,
+    algorithm: HashAlgorithm,
+) -> ScanResult {
+    Scanner::new(&[dir])
+        .filters(filters)
+        .algorithm(algorithm)
+        .run()
+}
+
+/// Like [`scan_directory_with_stats`], but takes full hashes from `cache` for files whose
+/// device, inode, size and mtime are unchanged, and adds newly computed hashes to it.
+///
+/// The cache is only updated in memory; call [`HashCache::save`] to persist it.
+pub fn scan_directory_with_cache<P: AsRef<Path>>(
+    dir: P,
+    filters: &FilterOptions,
+    algorithm: HashAlgorithm,
+    cache: &HashCache,
+) -> ScanResult {
+    Scanner::new(&[dir])
+        .filters(filters)
+        .algorithm(algorithm)
+        .cache(cache)
+        .run()
+}
+
+/// Scans several directories as one: duplicates are found within and across them.
+///
+/// Each [`FileEntry`] records which root it was found under. Roots may overlap or nest;
+/// every file is still counted once, and belongs to the innermost root that contains it.
+/// With a `cache`, full hashes are reused as in [`scan_directory_with_cache`].
+pub fn scan_roots<P: AsRef<Path>>(
+    roots: &[P],
+    filters: &FilterOptions,
+    algorithm: HashAlgorithm,
+    cache: Option<&HashCache>,
+) -> ScanResult {
+    let scanner = Scanner::new(roots).filters(filters).algorithm(algorithm);
+    match cache {
+        Some(cache) => scanner.cache(cache).run(),
+        None => scanner.run(),
+    }
+}
+
+/// A progress event emitted by a [`Scanner`].
+///
+/// Events are emitted from the scanning threads as the work happens, so handlers should
+/// be quick.
+#[derive(Debug, Clone, Copy)]
+pub enum ScanEvent<'a> {
+    /// The walk entered a directory.
+    DirectoryEntered(&'a Path),
+    /// The walk found a file. Filters are applied afterwards.
+    FileDiscovered(&'a Path),
+    /// A stage is about to look at `candidates` files, reading `bytes` bytes in total.
+    StageStarted {
+        name: &'static str,
+        candidates: usize,
+        bytes: u64,
+    },
+    /// The current stage is done with one file, having read this many bytes of it.
+    /// Files whose hash came from the cache count as read.
+    BytesHashed(u64),
+    /// A stage finished.
+    StageComplete(&'a StageStats),
+    /// A duplicate group was confirmed by the full hash stage.
+    GroupConfirmed(&'a DuplicateGroup),
+}
+
+/// Stops a [`Scanner`] early. Clones share one flag, so a clone can be handed to the scan
+/// and cancelled from another thread or a signal handler.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks every scan holding this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A duplicate scan that reports its progress and can be cancelled.
+///
+/// Works like [`scan_roots`], which it backs, but emits a [`ScanEvent`] for every
+/// directory, file, stage and group along the way. Once its [`CancellationToken`] is
+/// cancelled, the scan stops at the next file and returns what it has:
+/// [`ScanResult::cancelled`] is set and `groups` holds the groups confirmed so far.
 ///
 /// # Example
 ///
 /// ```text
-/// // let files = collect_files_recursively(Path::new("/some/path"));
+/// // let token = CancellationToken::new();
+/// // let result = Scanner::new(&["/data"])
+/// //     .algorithm(HashAlgorithm::Blake3)
+/// //     .cancel_token(token.clone())
+/// //     .on_event(|event| eprintln!("{event:?}"))
+/// //     .run();
 /// ```
-fn collect_files_recursively(dir: &Path) -> Vec<PathBuf> {
-    // Vector to store all found file paths
-    let mut all_files = Vec::new();
-    // Read directory entries, if possible
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_dir() {
+pub struct Scanner<'a> {
+    roots: Vec<PathBuf>,
+    filters: Option<&'a FilterOptions>,
+    algorithm: HashAlgorithm,
+    cache: Option<&'a HashCache>,
+    cancel: CancellationToken,
+    on_event: Option<EventHandler<'a>>,
+}
+
+/// A callback receiving [`ScanEvent`]s.
+type EventHandler<'a> = Box<dyn Fn(&ScanEvent<'_>) + Sync + 'a>;
+
+impl<'a> Scanner<'a> {
+    /// Creates a scan of `roots` with default filters and SHA-256.
+    pub fn new<P: AsRef<Path>>(roots: &[P]) -> Self {
+        Scanner {
+            roots: roots
+                .iter()
+                .map(|root| root.as_ref().to_path_buf())
+                .collect(),
+            filters: None,
+            algorithm: HashAlgorithm::default(),
+            cache: None,
+            cancel: CancellationToken::new(),
+            on_event: None,
+        }
+    }
+
+    pub fn filters(mut self, filters: &'a FilterOptions) -> Self {
+        self.filters = Some(filters);
+        self
+    }
+
+    pub fn algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Reuses and records full hashes in `cache`, as [`scan_directory_with_cache`] does.
+    pub fn cache(mut self, cache: &'a HashCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Calls `handler` with every [`ScanEvent`], from whichever thread emits it.
+    pub fn on_event(mut self, handler: impl Fn(&ScanEvent<'_>) + Sync + 'a) -> Self {
+        self.on_event = Some(Box::new(handler));
+        self
+    }
+
+    /// Runs the scan.
+    pub fn run(&self) -> ScanResult {
+        let default_filters = FilterOptions::default();
+        let filters = self.filters.unwrap_or(&default_filters);
+        let mut errors = Vec::new();
+
+        // Walk the innermost roots first: directories are entered once, so a nested root
+        // claims its files before an enclosing root reaches them
+        let mut order: Vec<usize> = (0..self.roots.len()).collect();
+        order.sort_by_key(|&index| {
+            let root = &self.roots[index];
+            let root = fs::canonicalize(root).unwrap_or_else(|_| root.clone());
+            Reverse(root.components().count())
+        });
+
+        // Collect all files recursively from the directories
+        let mut walk = Walk::new(self, filters, &mut errors);
+        for index in order {
+            walk.root(index, &self.roots[index]);
+        }
+        let (files, symlinks) = walk.finish();
+
+        let mut stages = Vec::new();
+        let mut scanned = (0, 0);
+        let groups = self.find_duplicates(files, filters, &mut stages, &mut scanned, &mut errors);
+
+        ScanResult {
+            roots: self.roots.clone(),
+            groups,
+            algorithm: self.algorithm,
+            filters: filters.settings(),
+            symlinks,
+            stages,
+            files_scanned: scanned.0,
+            bytes_scanned: scanned.1,
+            errors,
+            cancelled: self.is_cancelled(),
+        }
+    }
+
+    /// Runs the stages over the files found by the walk, stopping early if cancelled.
+    /// `scanned` receives the number of files considered and their total size.
+    fn find_duplicates(
+        &self,
+        files: Vec<(PathBuf, usize)>,
+        filters: &FilterOptions,
+        stages: &mut Vec<StageStats>,
+        scanned: &mut (usize, u64),
+        errors: &mut Vec<Error>,
+    ) -> Vec<DuplicateGroup> {
+        if self.is_cancelled() {
+            return Vec::new();
+        }
+        let hasher = self.algorithm.hasher();
+
+        // Filter files based on the provided filter options
+        let filtered_files: Vec<(PathBuf, usize)> = files
+            .into_iter()
+            .filter(|(path, _)| filters.matches(path))
+            .collect();
+
+        // Record metadata up front so later destructive actions can detect changes
+        let captured: Vec<Result<FileEntry, Error>> = filtered_files
+            .into_par_iter()
+            .filter(|_| !self.is_cancelled())
+            .map(|(path, root)| match FileEntry::capture(&path) {
+                Ok(entry) => Ok(FileEntry { root, ..entry }),
+                Err(e) => Err(Error::io(path, e)),
+            })
+            .collect();
+        let mut entries = HashMap::with_capacity(captured.len());
+        for entry in captured {
+            match entry {
+                Ok(entry) => {
+                    entries.insert(entry.path.clone(), entry);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        // Hard links share their data, so only the first name of each inode is a candidate
+        let mut links: HashMap<(u64, u64), Vec<PathBuf>> = HashMap::new();
+        for entry in entries.values() {
+            links
+                .entry(entry.inode())
+                .or_default()
+                .push(entry.path.clone());
+        }
+        for paths in links.values_mut() {
+            paths.sort();
+        }
+        *scanned = (
+            entries.len(),
+            links.values().map(|paths| entries[&paths[0]].size).sum(),
+        );
+
+        if self.is_cancelled() {
+            return Vec::new();
+        }
+
+        // Stage 1: a file with a unique size cannot have a duplicate
+        let input = links.len();
+        self.start_stage("size", input, 0);
+        let (by_size, complete) = self.group_candidates(
+            links.values().map(|paths| paths[0].clone()).collect(),
+            |path| Ok(entries[path].size),
+            errors,
+        );
+        let by_size = by_size.into_values().collect();
+        let candidates: Vec<PathBuf> = self
+            .finish_stage(stages, "size", input, by_size, complete)
+            .into_iter()
+            .flatten()
+            .collect();
+        if self.is_cancelled() {
+            return Vec::new();
+        }
+
+        // Stage 2: compare a cheap sample from the start and end of each file
+        let input = candidates.len();
+        let sampled = |path: &Path| entries[path].size.min(2 * SAMPLE_SIZE);
+        let bytes = candidates.iter().map(|path| sampled(path));
+        self.start_stage("sample", input, bytes.sum());
+        let (by_sample, complete) = self.group_candidates(
+            candidates,
+            |path| {
+                let digest = hash_file_sample(path, SAMPLE_SIZE, hasher);
+                self.emit(ScanEvent::BytesHashed(sampled(path)));
+                digest.map_err(|e| Error::io(path, e))
+            },
+            errors,
+        );
+        let by_sample = by_sample.into_values().collect();
+        let candidates = self.finish_stage(stages, "sample", input, by_sample, complete);
+        if self.is_cancelled() {
+            return Vec::new();
+        }
+
+        // Stage 3: full content hash of the remaining candidates. Files with different
+        // samples cannot be equal, so each sample group is hashed and confirmed on its own
+        let input = candidates.iter().map(Vec::len).sum();
+        let bytes = candidates.iter().flatten().map(|path| entries[path].size);
+        self.start_stage("full", input, bytes.sum());
+        let full_hash = |path: &Path| {
+            let digest = match self.cache {
+                Some(cache) => cache.hash_file(path, self.algorithm),
+                None => hash_file_with(path, hasher),
+            };
+            self.emit(ScanEvent::BytesHashed(entries[path].size));
+            digest.map_err(|e| Error::io(path, e))
+        };
+        let confirmed: Vec<(Vec<DuplicateGroup>, Option<usize>, Vec<Error>)> = candidates
+            .into_par_iter()
+            .map(|candidates| {
+                let mut errors = Vec::new();
+                let input = candidates.len();
+                let (by_hash, complete) = self.group_candidates(candidates, full_hash, &mut errors);
+                // Unread members could have matched the ones left over, so only a sample
+                // group hashed in full rules anything out
+                let remaining: usize = by_hash.values().map(Vec::len).sum();
+                let removed = complete.then(|| input - remaining);
+                // A group cut short by cancellation may be missing members
+                if self.is_cancelled() {
+                    return (Vec::new(), removed, errors);
+                }
+                let groups: Vec<DuplicateGroup> = by_hash
+                    .into_iter()
+                    .map(|(digest, candidates)| {
+                        // Bring back the other names of each candidate's inode
+                        let mut paths: Vec<&PathBuf> = candidates
+                            .iter()
+                            .flat_map(|path| &links[&entries[path].inode()])
+                            .collect();
+                        paths.sort();
+                        let files: Vec<FileEntry> =
+                            paths.iter().map(|path| entries[*path].clone()).collect();
+                        let group = DuplicateGroup {
+                            digest,
+                            size: files[0].size,
+                            files,
+                        };
+                        self.emit(ScanEvent::GroupConfirmed(&group));
+                        group
+                    })
+                    .collect();
+                (groups, removed, errors)
+            })
+            .collect();
+
+        let mut groups = Vec::new();
+        let (mut removed, mut complete) = (0, true);
+        for (confirmed, group_removed, group_errors) in confirmed {
+            groups.extend(confirmed);
+            match group_removed {
+                Some(count) => removed += count,
+                None => complete = false,
+            }
+            errors.extend(group_errors);
+        }
+        let stats = StageStats {
+            name: "full",
+            candidates: input,
+            removed,
+            complete,
+        };
+        self.emit(ScanEvent::StageComplete(&stats));
+        stages.push(stats);
+
+        // Groups were confirmed in parallel, so restore a stable order
+        groups.sort_by(|a, b| a.digest.cmp(&b.digest));
+        groups
+    }
+
+    /// Groups `files` by the key computed in parallel by `key`, keeping only groups
+    /// with more than one member. Files for which no key can be computed are dropped
+    /// and their errors appended to `errors`. Once cancelled, remaining files are skipped;
+    /// the returned flag tells whether every file was keyed.
+    fn group_candidates<K, F>(
+        &self,
+        files: Vec<PathBuf>,
+        key: F,
+        errors: &mut Vec<Error>,
+    ) -> (HashMap<K, Vec<PathBuf>>, bool)
+    where
+        K: Eq + Hash + Send,
+        F: Fn(&Path) -> Result<K, Error> + Sync,
+    {
+        let input = files.len();
+        let keyed: Vec<Result<(K, PathBuf), Error>> = files
+            .into_par_iter()
+            .filter(|_| !self.is_cancelled())
+            .map(|path| key(&path).map(|k| (k, path)))
+            .collect();
+        let complete = keyed.len() == input;
+
+        let mut groups: HashMap<K, Vec<PathBuf>> = HashMap::new();
+        for result in keyed {
+            match result {
+                Ok((k, path)) => groups.entry(k).or_default().push(path),
+                Err(e) => errors.push(e),
+            }
+        }
+        groups.retain(|_, paths| paths.len() > 1);
+        (groups, complete)
+    }
+
+    fn start_stage(&self, name: &'static str, candidates: usize, bytes: u64) {
+        self.emit(ScanEvent::StageStarted {
+            name,
+            candidates,
+            bytes,
+        });
+    }
+
+    /// Records the statistics of a finished stage and returns its surviving groups.
+    /// An incomplete stage rules nothing out for certain.
+    fn finish_stage(
+        &self,
+        stages: &mut Vec<StageStats>,
+        name: &'static str,
+        input: usize,
+        groups: Vec<Vec<PathBuf>>,
+        complete: bool,
+    ) -> Vec<Vec<PathBuf>> {
+        let survivors: usize = groups.iter().map(Vec::len).sum();
+        let stats = StageStats {
+            name,
+            candidates: input,
+            removed: if complete { input - survivors } else { 0 },
+            complete,
+        };
+        self.emit(ScanEvent::StageComplete(&stats));
+        stages.push(stats);
+        groups
+    }
+
+    fn emit(&self, event: ScanEvent<'_>) {
+        if let Some(handler) = &self.on_event {
+            handler(&event);
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+}
+
+/// The state of one walk of a directory tree.
+struct Walk<'a> {
+    /// The scan the walk belongs to, for events and cancellation.
+    scanner: &'a Scanner<'a>,
+    /// The root being walked and its index.
+    root: PathBuf,
+    root_index: usize,
+    filters: &'a FilterOptions,
+    /// Ignore files of the directories being walked, outermost first.
+    ignores: Vec<Gitignore>,
+    /// `(dev, ino)` of every directory entered, so none is walked twice and loops end.
+    visited: HashSet<(u64, u64)>,
+    /// Files found, with the index of their root.
+    files: Vec<(PathBuf, usize)>,
+    /// Followed file symlinks, with the `(dev, ino)` of their target and their root index.
+    targets: Vec<(PathBuf, (u64, u64), usize)>,
+    /// Followed directory symlinks still to walk, with their root index and the ignore
+    /// files in effect where they were found.
+    linked_dirs: Vec<(PathBuf, usize, Vec<Gitignore>)>,
+    /// Symlinks seen, when they are reported rather than followed.
+    reported: Vec<PathBuf>,
+    errors: &'a mut Vec<Error>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(
+        scanner: &'a Scanner<'a>,
+        filters: &'a FilterOptions,
+        errors: &'a mut Vec<Error>,
+    ) -> Self {
+        Walk {
+            scanner,
+            root: PathBuf::new(),
+            root_index: 0,
+            filters,
+            ignores: Vec::new(),
+            visited: HashSet::new(),
+            files: Vec::new(),
+            targets: Vec::new(),
+            linked_dirs: Vec::new(),
+            reported: Vec::new(),
+            errors,
+        }
+    }
+
+    /// Walks the root with the given index. Directories already walked from an earlier
+    /// root are skipped.
+    fn root(&mut self, index: usize, root: &Path) {
+        self.root = root.to_path_buf();
+        self.root_index = index;
+        self.dir(root);
+    }
+
+    /// Recursively collects all files under `dir`.
+    ///
+    /// Entries are classified without following symlinks; what happens to a symlink is
+    /// up to the [`SymlinkPolicy`]. Excluded and ignored entries are skipped, directories
+    /// without being entered. Directories that cannot be read are recorded as errors.
+    /// Nothing more is entered once the scan is cancelled.
+    fn dir(&mut self, dir: &Path) {
+        if self.scanner.is_cancelled() {
+            return;
+        }
+        let metadata = match fs::metadata(dir) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                self.errors.push(Error::io(dir, e));
+                return;
+            }
+        };
+        // Already walked, through another path or a symlink loop
+        if !self.visited.insert((metadata.dev(), metadata.ino())) {
+            return;
+        }
+        self.scanner.emit(ScanEvent::DirectoryEntered(dir));
+
+        // Read directory entries, reporting directories we cannot list
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.errors.push(Error::io(dir, e));
+                return;
+            }
+        };
+        let ignores = self.ignores.len();
+        if let Some(ignore) = self.load_ignore_files(dir) {
+            self.ignores.push(ignore);
+        }
+
+        for entry in entries {
+            if self.scanner.is_cancelled() {
+                break;
+            }
+            let (path, file_type) =
+                match entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))) {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        self.errors.push(Error::io(dir, e));
+                        continue;
+                    }
+                };
+            if self.skips(&path, file_type.is_dir()) {
+                continue;
+            }
+            if file_type.is_dir() {
                 // Recurse into subdirectories
-                all_files.extend(collect_files_recursively(&path));
-            } else if path.is_file() {
+                self.dir(&path);
+            } else if file_type.is_file() {
                 // Add file to the list
-                all_files.push(path);
+                self.scanner.emit(ScanEvent::FileDiscovered(&path));
+                self.files.push((path, self.root_index));
+            } else if file_type.is_symlink() {
+                self.symlink(path);
+            }
+        }
+        self.ignores.truncate(ignores);
+    }
+
+    /// Reads the ignore files in `dir` into one matcher, or returns `None` if it has none.
+    ///
+    /// Later files take precedence: `.gitignore`, then `.ignore`, then `.hashlaserignore`.
+    fn load_ignore_files(&mut self, dir: &Path) -> Option<Gitignore> {
+        let names: &[&str] = if self.filters.ignore_files {
+            &[".gitignore", ".ignore", ".hashlaserignore"]
+        } else {
+            &[".hashlaserignore"]
+        };
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+        for name in names {
+            let path = dir.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            found = true;
+            if let Some(e) = builder.add(&path) {
+                self.errors.push(Error::invalid_path(path, e.to_string()));
+            }
+        }
+        if !found {
+            return None;
+        }
+        match builder.build() {
+            Ok(ignore) => Some(ignore),
+            Err(e) => {
+                self.errors.push(Error::invalid_path(dir, e.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Whether the entry at `path` is excluded by the filters or an ignore file.
+    fn skips(&self, path: &Path, is_dir: bool) -> bool {
+        if self
+            .filters
+            .excludes(path, path.strip_prefix(&self.root).ok(), is_dir)
+        {
+            return true;
+        }
+        // The innermost ignore file with a matching rule decides
+        for ignore in self.ignores.iter().rev() {
+            if !path.starts_with(ignore.path()) {
+                continue;
+            }
+            match ignore.matched(path, is_dir) {
+                Match::None => {}
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+        false
+    }
+
+    /// Handles a symlink found in a directory.
+    fn symlink(&mut self, path: PathBuf) {
+        match self.filters.symlinks {
+            SymlinkPolicy::Skip => {}
+            SymlinkPolicy::Report => self.reported.push(path),
+            // The link keeps its own path, so everything found through it stays under the
+            // root and the filters see the same relative paths as for any other entry
+            SymlinkPolicy::Follow => match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_dir() && self.skips(&path, true) => {}
+                Ok(metadata) if metadata.is_dir() => {
+                    let ignores = self.ignores.clone();
+                    self.linked_dirs.push((path, self.root_index, ignores));
+                }
+                Ok(metadata) if metadata.is_file() => {
+                    let inode = (metadata.dev(), metadata.ino());
+                    self.targets.push((path, inode, self.root_index));
+                }
+                Ok(_) => {}
+                // A dangling link has no contents to compare
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => self.errors.push(Error::io(path, e)),
+            },
+        }
+    }
+
+    /// Walks the followed directory symlinks, then returns the files found and the
+    /// symlinks reported.
+    ///
+    /// Linked directories are walked only after every root, so a directory that can be
+    /// reached under a name of its own is found there. Likewise, a followed file symlink
+    /// is kept only if the walk did not already find its target under a name of its own.
+    fn finish(mut self) -> (Vec<(PathBuf, usize)>, Vec<PathBuf>) {
+        while let Some((path, root_index, ignores)) = self.linked_dirs.pop() {
+            self.root = self.scanner.roots[root_index].clone();
+            self.root_index = root_index;
+            self.ignores = ignores;
+            self.dir(&path);
+        }
+        if !self.targets.is_empty() {
+            let mut found: HashSet<(u64, u64)> = self
+                .files
+                .iter()
+                .filter_map(|(path, _)| fs::metadata(path).ok())
+                .map(|metadata| (metadata.dev(), metadata.ino()))
+                .collect();
+            // The same target may be linked from several places; keep it once
+            for (link, inode, root) in self.targets {
+                if found.insert(inode) {
+                    self.files.push((link, root));
+                }
             }
         }
+        (self.files, self.reported)
     }
-    all_files
 }
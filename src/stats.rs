@@ -0,0 +1,157 @@
+//! How much space duplicates waste.
+//!
+//! [`ScanStats`] sums up a scan: how much was scanned, how many groups were found, how
+//! many bytes keeping one copy of each would free, and where those bytes are. Which copy
+//! counts as the original follows a [`KeepPolicy`], so the directories listed are the
+//! ones a delete run with that policy would clean up.
+
+use crate::{
+    keep_policy::KeepPolicy,
+    scanner::{DuplicateGroup, FileEntry, ScanResult},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+/// Number of groups and directories listed when no other limit is given.
+pub const DEFAULT_TOP: usize = 10;
+
+/// A summary of the wasted space found by a scan. All sizes are in bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanStats {
+    /// Files that passed the filters and were considered.
+    pub files_scanned: usize,
+    /// Total size of those files, each inode counted once.
+    pub bytes_scanned: u64,
+    /// Number of duplicate groups.
+    pub groups: usize,
+    /// Files that are neither the kept copy of their group nor a hard link to it.
+    pub redundant_files: usize,
+    /// Bytes freed by keeping one copy of every group: size × (copies − 1), where hard
+    /// links to one inode are a single copy.
+    pub reclaimable_bytes: u64,
+    /// The groups that free the most bytes, largest first.
+    pub largest_groups: Vec<GroupStats>,
+    /// The directories holding the most redundant bytes, largest first.
+    pub directories: Vec<DirectoryStats>,
+}
+
+/// One entry of [`ScanStats::largest_groups`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupStats {
+    pub hash: String,
+    /// Size of each file.
+    pub size: u64,
+    pub files: usize,
+    pub reclaimable_bytes: u64,
+    /// The copy the keep policy preserves.
+    #[serde(with = "crate::path_encoding")]
+    pub keep: PathBuf,
+}
+
+/// One entry of [`ScanStats::directories`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryStats {
+    #[serde(with = "crate::path_encoding")]
+    pub path: PathBuf,
+    /// Redundant files directly in the directory.
+    pub redundant_files: usize,
+    /// Bytes freed by removing them. A file whose inode survives under another name, or
+    /// was already counted, frees nothing.
+    pub redundant_bytes: u64,
+}
+
+impl ScanStats {
+    /// Summarizes `scan`, treating the file `keep` chooses in each group as the original,
+    /// and listing the `top` largest groups and directories.
+    pub fn from_scan(scan: &ScanResult, keep: &KeepPolicy, top: usize) -> Self {
+        let mut largest = Vec::with_capacity(scan.groups.len());
+        let mut directories: BTreeMap<&Path, (usize, u64)> = BTreeMap::new();
+        let mut redundant_files = 0;
+
+        for group in &scan.groups {
+            let kept = &group.files[keep.choose(&group.files).index];
+            largest.push(GroupStats {
+                hash: group.digest.clone(),
+                size: group.size,
+                files: group.files.len(),
+                reclaimable_bytes: group.reclaimable_bytes(),
+                keep: kept.path.clone(),
+            });
+
+            // The first redundant name of each inode frees its bytes; further names free
+            // nothing. Names of the kept inode survive along with it, and so do symlinks.
+            let mut counted: HashSet<(u64, u64)> = HashSet::new();
+            let redundant = group
+                .files
+                .iter()
+                .filter(|file| file.inode() != kept.inode() && !file.path.is_symlink());
+            for file in redundant {
+                redundant_files += 1;
+                let entry = directories.entry(parent(file)).or_default();
+                entry.0 += 1;
+                if counted.insert(file.inode()) {
+                    entry.1 += group.size;
+                }
+            }
+        }
+
+        largest.sort_by(|a, b| {
+            b.reclaimable_bytes
+                .cmp(&a.reclaimable_bytes)
+                .then_with(|| a.hash.cmp(&b.hash))
+        });
+        largest.truncate(top);
+
+        let mut directories: Vec<DirectoryStats> = directories
+            .into_iter()
+            .map(|(path, (files, bytes))| DirectoryStats {
+                path: path.to_path_buf(),
+                redundant_files: files,
+                redundant_bytes: bytes,
+            })
+            .collect();
+        directories.sort_by(|a, b| {
+            b.redundant_bytes
+                .cmp(&a.redundant_bytes)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        directories.truncate(top);
+
+        ScanStats {
+            files_scanned: scan.files_scanned,
+            bytes_scanned: scan.bytes_scanned,
+            groups: scan.groups.len(),
+            redundant_files,
+            reclaimable_bytes: scan
+                .groups
+                .iter()
+                .map(DuplicateGroup::reclaimable_bytes)
+                .sum(),
+            largest_groups: largest,
+            directories,
+        }
+    }
+}
+
+/// The directory `file` is in.
+fn parent(file: &FileEntry) -> &Path {
+    file.path.parent().unwrap_or(Path::new(""))
+}
+
+/// Formats a byte count with binary units, e.g. `1.5 MiB`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{value:.1} {}", UNITS[unit]),
+    }
+}
@@ -0,0 +1,223 @@
+//! Moving files to the freedesktop.org trash.
+//!
+//! Implements the parts of the [XDG Trash specification] needed to trash regular files:
+//! the home trash at `$XDG_DATA_HOME/Trash`, per-mount `$topdir/.Trash/$uid` and
+//! `$topdir/.Trash-$uid` directories, and `.trashinfo` files recording the original path
+//! and deletion time. Files trashed this way can be listed and restored by desktop file
+//! managers.
+//!
+//! [XDG Trash specification]: https://specifications.freedesktop.org/trash-spec/latest/
+
+use crate::fs_util::xdg_dir;
+use chrono::Local;
+use std::{
+    env,
+    ffi::OsStr,
+    fs::{self, DirBuilder, OpenOptions},
+    io::{self, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{DirBuilderExt, MetadataExt},
+    },
+    path::{Path, PathBuf},
+};
+
+/// A file that has been moved to a trash directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashedFile {
+    /// Where the file lived before it was trashed.
+    pub original: PathBuf,
+    /// The file's new location under `<trash>/files`.
+    pub files_path: PathBuf,
+    /// The matching `.trashinfo` file under `<trash>/info`.
+    pub info_path: PathBuf,
+}
+
+/// Moves files into the trash directory appropriate for their filesystem.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    home: PathBuf,
+}
+
+impl Trash {
+    /// Uses the home trash at `$XDG_DATA_HOME/Trash`, falling back to
+    /// `$HOME/.local/share/Trash` when `XDG_DATA_HOME` is unset.
+    pub fn from_env() -> io::Result<Self> {
+        let data_home = xdg_dir("XDG_DATA_HOME", ".local/share")?;
+        Ok(Trash::with_home(data_home.join("Trash")))
+    }
+
+    /// Uses `home` as the home trash directory instead of the one from the environment.
+    pub fn with_home<P: Into<PathBuf>>(home: P) -> Self {
+        Trash { home: home.into() }
+    }
+
+    /// Returns the home trash directory.
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    /// Moves the file at `path` to the trash.
+    ///
+    /// The home trash is used when the file lives on the same filesystem; otherwise the
+    /// file goes to the trash at the top of its own mount point. Files are never copied
+    /// across filesystems.
+    pub fn trash_file<P: AsRef<Path>>(&self, path: P) -> io::Result<TrashedFile> {
+        let original = absolute_path(path.as_ref())?;
+        let device = fs::symlink_metadata(&original)?.dev();
+
+        let (trash_dir, info_path_value) = if device_of_nearest(&self.home)? == device {
+            fs::create_dir_all(self.home.join("files"))?;
+            fs::create_dir_all(self.home.join("info"))?;
+            (self.home.clone(), original.clone())
+        } else {
+            let topdir = mount_top(&original, device)?;
+            let trash_dir = topdir_trash(&topdir)?;
+            // Per-mount trashes record paths relative to the mount point
+            let relative = original
+                .strip_prefix(&topdir)
+                .unwrap_or(&original)
+                .to_path_buf();
+            (trash_dir, relative)
+        };
+
+        let name = original
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+        let (info_path, files_path) = reserve_name(&trash_dir, name, &info_path_value)?;
+
+        if let Err(e) = fs::rename(&original, &files_path) {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+
+        Ok(TrashedFile {
+            original,
+            files_path,
+            info_path,
+        })
+    }
+}
+
+/// Creates a uniquely named `.trashinfo` file for `name` in `trash_dir/info` and returns
+/// its path together with the matching path under `trash_dir/files`.
+///
+/// The info file is created with `O_EXCL`, which is how the specification reserves a name.
+fn reserve_name(trash_dir: &Path, name: &OsStr, original: &Path) -> io::Result<(PathBuf, PathBuf)> {
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(original.as_os_str().as_bytes()),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    for attempt in 1u32.. {
+        let mut candidate = name.to_os_string();
+        if attempt > 1 {
+            candidate.push(format!(".{attempt}"));
+        }
+        let files_path = trash_dir.join("files").join(&candidate);
+        let mut info_name = candidate;
+        info_name.push(".trashinfo");
+        let info_path = trash_dir.join("info").join(info_name);
+
+        if files_path.symlink_metadata().is_ok() {
+            continue;
+        }
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(mut file) => {
+                file.write_all(contents.as_bytes())?;
+                return Ok((info_path, files_path));
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("ran out of trash names")
+}
+
+/// Returns the trash directory for the mount point `topdir`, creating it if needed.
+///
+/// Prefers an administrator-provided `$topdir/.Trash/$uid` when `$topdir/.Trash` is a real
+/// directory with the sticky bit set, and otherwise uses `$topdir/.Trash-$uid`.
+fn topdir_trash(topdir: &Path) -> io::Result<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+
+    // The shared trash must be a real directory with the sticky bit (0o1000) set
+    let shared = topdir.join(".Trash");
+    if let Ok(metadata) = fs::symlink_metadata(&shared)
+        && metadata.is_dir()
+        && metadata.mode() & 0o1000 != 0
+    {
+        let dir = shared.join(uid.to_string());
+        if create_private_trash(&dir).is_ok() {
+            return Ok(dir);
+        }
+    }
+
+    let dir = topdir.join(format!(".Trash-{uid}"));
+    create_private_trash(&dir)?;
+    Ok(dir)
+}
+
+/// Creates `dir` and its `files` and `info` subdirectories with mode 0700.
+fn create_private_trash(dir: &Path) -> io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true).mode(0o700);
+    builder.create(dir.join("files"))?;
+    builder.create(dir.join("info"))
+}
+
+/// Walks up from `path` to the top directory of the mount holding `device`.
+fn mount_top(path: &Path, device: u64) -> io::Result<PathBuf> {
+    let mut top = path.parent().unwrap_or(path).to_path_buf();
+    while let Some(parent) = top.parent() {
+        if fs::metadata(parent)?.dev() != device {
+            break;
+        }
+        top = parent.to_path_buf();
+    }
+    Ok(top)
+}
+
+/// Returns the device of `path`, or of its nearest existing ancestor.
+fn device_of_nearest(path: &Path) -> io::Result<u64> {
+    let mut current = path;
+    loop {
+        match fs::metadata(current) {
+            Ok(metadata) => return Ok(metadata.dev()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                current = current.parent().ok_or(e)?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Makes `path` absolute without resolving a symlink in its final component.
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => env::current_dir()?,
+    };
+    match path.file_name() {
+        Some(name) => Ok(parent.join(name)),
+        None => fs::canonicalize(path),
+    }
+}
+
+/// Percent-encodes a path as required for the `Path=` key of a `.trashinfo` file.
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        if byte.is_ascii_alphanumeric() || b"-_.~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
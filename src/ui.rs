@@ -1,7 +1,179 @@
+use crate::cli::OutputFormat;
 use colored::*;
+use indicatif::{ProgressBar, ProgressStyle};
+use intelligent_file_deduplicator::{
+    error::Error,
+    hashing::HashAlgorithm,
+    report::ReportFormat,
+    scanner::{DuplicateGroup, ScanEvent, StageStats},
+    stats::{ScanStats, human_size},
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+/// The `--format` of this run.
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Records held back until the command finishes, in json mode.
+static RECORDS: Mutex<Vec<serde_json::Value>> = Mutex::new(Vec::new());
+
+/// One machine-readable record, written to stdout in json and ndjson modes. Its `type`
+/// field names the variant, e.g. `{"type": "group", ...}`.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Record<'a> {
+    /// The verdict of `compare`.
+    Compare {
+        #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+        file1: &'a Path,
+        #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+        file2: &'a Path,
+        algorithm: HashAlgorithm,
+        identical: bool,
+    },
+    /// A duplicate group found by a scan.
+    Group {
+        hash: &'a str,
+        size: u64,
+        reclaimable_bytes: u64,
+        files: Vec<GroupFile<'a>>,
+    },
+    /// A stage of a scan and how many candidates it ruled out.
+    Stage {
+        name: &'a str,
+        candidates: usize,
+        removed: usize,
+        complete: bool,
+    },
+    /// A symlink that was not followed.
+    Symlink {
+        #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+        path: &'a Path,
+    },
+    /// A path that could not be processed.
+    Error {
+        #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+        path: &'a Path,
+        message: String,
+    },
+    /// The totals of a scan.
+    ScanSummary {
+        groups: usize,
+        reclaimable_bytes: u64,
+        cancelled: bool,
+    },
+    /// The wasted-space summary of `stats`, with sizes in bytes.
+    Stats(&'a ScanStats),
+    /// A report written by `report`.
+    Report {
+        #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+        path: &'a Path,
+        format: ReportFormat,
+        groups: usize,
+    },
+    /// The totals of a `delete` or `apply` run.
+    DeleteSummary {
+        processed: usize,
+        skipped: usize,
+        errors: usize,
+    },
+    /// The undo journal a run wrote.
+    Journal {
+        #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+        path: &'a Path,
+    },
+    /// A file put back by `restore`.
+    Restored {
+        #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+        path: &'a Path,
+    },
+    /// A file `restore` could not put back.
+    Unrestorable {
+        #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+        path: &'a Path,
+        reason: &'a str,
+    },
+    /// The contents of the hash cache.
+    CacheStats {
+        #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+        path: &'a Path,
+        entries: usize,
+        by_algorithm: BTreeMap<&'static str, usize>,
+        file_size: Option<u64>,
+    },
+    /// The outcome of `cache prune`.
+    CachePruned { removed: usize },
+    /// The outcome of `cache clear`.
+    CacheCleared { existed: bool },
+}
+
+/// One file of a [`Record::Group`].
+#[derive(Serialize)]
+pub struct GroupFile<'a> {
+    #[serde(with = "intelligent_file_deduplicator::path_encoding")]
+    path: &'a Path,
+    /// Index of the scanned directory the file was found under.
+    root: usize,
+    /// Whether the file is a hard link to another file of the group.
+    hard_link: bool,
+}
+
+/// Sets the output format. In json and ndjson modes colors are turned off and messages
+/// go to stderr, leaving stdout to the records.
+pub fn set_output_format(format: OutputFormat) {
+    FORMAT.get_or_init(|| format);
+    if machine_readable() {
+        colored::control::set_override(false);
+    }
+}
+
+/// Whether stdout is reserved for records rather than text.
+pub fn machine_readable() -> bool {
+    FORMAT
+        .get()
+        .is_some_and(|&format| format != OutputFormat::Text)
+}
+
+/// Writes `record` to stdout: straight away in ndjson mode, or when the command
+/// finishes in json mode. Does nothing in text mode.
+pub fn emit<T: Serialize + ?Sized>(record: &T) {
+    let value = serde_json::to_value(record).expect("records serialize to JSON");
+    match FORMAT.get() {
+        Some(OutputFormat::Ndjson) => println!("{value}"),
+        Some(OutputFormat::Json) => RECORDS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(value),
+        _ => {}
+    }
+}
+
+/// Writes the records held back in json mode, as one array.
+pub fn finish_output() {
+    if FORMAT.get() == Some(&OutputFormat::Json) {
+        let records = std::mem::take(&mut *RECORDS.lock().unwrap_or_else(|e| e.into_inner()));
+        let json = serde_json::to_string_pretty(&records).expect("records serialize to JSON");
+        println!("{json}");
+    }
+}
+
+/// Writes any held-back records, then exits with `code`.
+pub fn exit(code: i32) -> ! {
+    finish_output();
+    process::exit(code)
+}
 
 /// Displays the HashLaser banner in a stylish format.
 pub fn print_banner() {
+    if machine_readable() {
+        return;
+    }
     println!(
         "{}",
         r#"
@@ -23,11 +195,19 @@ pub fn print_banner() {
 
 /// Prints a success message in green.
 pub fn print_success(message: &str) {
+    if machine_readable() {
+        eprintln!("✅  {message}");
+        return;
+    }
     println!("{} {}", "✅ ".green(), message.green());
 }
 
 /// Prints a warning message in yellow.
 pub fn print_warning(message: &str) {
+    if machine_readable() {
+        eprintln!("⚠️  {message}");
+        return;
+    }
     println!("{} {}", "⚠️ ".yellow(), message.yellow());
 }
 
@@ -38,12 +218,223 @@ pub fn print_error(message: &str) {
 
 /// Prints an informational message in cyan.
 pub fn print_info(message: &str) {
+    if machine_readable() {
+        eprintln!("ℹ️  {message}");
+        return;
+    }
     println!("{} {}", "ℹ️ ".cyan(), message.cyan());
 }
 
-/// Prints a list of items with bullets.
-pub fn print_list(items: &[String]) {
+/// Prints a list of paths with bullets.
+pub fn print_list(items: &[PathBuf]) {
     for item in items {
-        println!("{} {}", "•".bright_blue(), item);
+        println!("{} {}", "•".bright_blue(), item.display());
+    }
+}
+
+/// Prints a duplicate group: its digest, size and reclaimable space, then its files.
+///
+/// Files that are hard links to another file of the group are marked, since removing
+/// them frees no space.
+pub fn print_group(group: &DuplicateGroup) {
+    if machine_readable() {
+        let files = group
+            .files
+            .iter()
+            .map(|file| GroupFile {
+                path: &file.path,
+                root: file.root,
+                hard_link: group.is_linked(file),
+            })
+            .collect();
+        emit(&Record::Group {
+            hash: &group.digest,
+            size: group.size,
+            reclaimable_bytes: group.reclaimable_bytes(),
+            files,
+        });
+        return;
+    }
+    println!(
+        "\n🧬 Hash: {} ({} files of {} bytes, {} bytes reclaimable)",
+        group.digest,
+        group.files.len(),
+        group.size,
+        group.reclaimable_bytes()
+    );
+    for file in &group.files {
+        if group.is_linked(file) {
+            println!(
+                "{} {} {}",
+                "•".bright_blue(),
+                file.path.display(),
+                "🔗 hard link".dimmed()
+            );
+        } else {
+            println!("{} {}", "•".bright_blue(), file.path.display());
+        }
+    }
+}
+
+/// Prints the space freed by keeping one copy of every group.
+pub fn print_reclaimable(groups: &[DuplicateGroup]) {
+    let bytes: u64 = groups.iter().map(DuplicateGroup::reclaimable_bytes).sum();
+    print_info(&format!(
+        "💽 {} bytes reclaimable across {} group(s)",
+        bytes,
+        groups.len()
+    ));
+}
+
+/// Prints the wasted-space summary of a scan with sizes in human units.
+pub fn print_stats(stats: &ScanStats) {
+    if machine_readable() {
+        emit(&Record::Stats(stats));
+        return;
+    }
+    let share = match stats.bytes_scanned {
+        0 => 0.0,
+        scanned => stats.reclaimable_bytes as f64 * 100.0 / scanned as f64,
+    };
+    println!(
+        "📁 Scanned {} file(s), {}",
+        stats.files_scanned,
+        human_size(stats.bytes_scanned)
+    );
+    println!(
+        "🧬 {} duplicate group(s), {} redundant file(s)",
+        stats.groups, stats.redundant_files
+    );
+    println!(
+        "{} {}",
+        "💽".cyan(),
+        format!(
+            "{} reclaimable ({share:.1}% of scanned)",
+            human_size(stats.reclaimable_bytes)
+        )
+        .cyan()
+        .bold()
+    );
+
+    if !stats.largest_groups.is_empty() {
+        println!("\n🏆 Largest groups:");
+        for group in &stats.largest_groups {
+            println!(
+                "{} {:>10}  {} × {:<10} {}",
+                "•".bright_blue(),
+                human_size(group.reclaimable_bytes),
+                group.files,
+                human_size(group.size),
+                group.keep.display()
+            );
+        }
+    }
+    if !stats.directories.is_empty() {
+        println!("\n📂 Directories with the most redundant bytes:");
+        for directory in &stats.directories {
+            println!(
+                "{} {:>10}  {:>5} file(s)  {}",
+                "•".bright_blue(),
+                human_size(directory.redundant_bytes),
+                directory.redundant_files,
+                directory.path.display()
+            );
+        }
+    }
+}
+
+/// Prints how many candidates each stage of the duplicate scan ruled out.
+pub fn print_stages(stages: &[StageStats]) {
+    for stage in stages {
+        if machine_readable() {
+            emit(&Record::Stage {
+                name: stage.name,
+                candidates: stage.candidates,
+                removed: stage.removed,
+                complete: stage.complete,
+            });
+            continue;
+        }
+        println!(
+            "{} {:<6} {} candidates, {} removed{}",
+            "⏱️".dimmed(),
+            stage.name,
+            stage.candidates,
+            stage.removed,
+            if stage.complete { "" } else { " (interrupted)" }
+        );
+    }
+}
+
+/// Live progress of a scan on stderr: a count of the files found while walking, then a
+/// bar per stage with throughput and ETA. Hidden when stderr is not a terminal.
+pub struct ScanProgress {
+    bar: ProgressBar,
+}
+
+impl ScanProgress {
+    pub fn new() -> Self {
+        let bar = ProgressBar::new_spinner();
+        bar.set_style(
+            ProgressStyle::with_template("{spinner:.cyan} 🔍 {human_pos} files found {msg}")
+                .expect("valid progress template"),
+        );
+        bar.enable_steady_tick(Duration::from_millis(100));
+        ScanProgress { bar }
+    }
+
+    /// Updates the display for one scan event.
+    pub fn update(&self, event: &ScanEvent<'_>) {
+        match event {
+            ScanEvent::FileDiscovered(_) => self.bar.inc(1),
+            ScanEvent::StageStarted { name, bytes, .. } if *bytes > 0 => {
+                self.bar.set_style(
+                    ProgressStyle::with_template(
+                        "{spinner:.cyan} ⏱️ {msg:<6} [{bar:25.cyan/blue}] {binary_bytes}/{binary_total_bytes} \
+                         ({binary_bytes_per_sec}, ETA {eta})",
+                    )
+                    .expect("valid progress template")
+                    .progress_chars("=> "),
+                );
+                self.bar.reset();
+                self.bar.set_length(*bytes);
+                self.bar.set_message(*name);
+            }
+            ScanEvent::BytesHashed(bytes) => self.bar.inc(*bytes),
+            _ => {}
+        }
+    }
+
+    /// Removes the progress display.
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Number of path errors listed before the rest are only counted.
+const MAX_LISTED_ERRORS: usize = 20;
+
+/// Summarizes the paths that could not be processed, on stderr. In json and ndjson
+/// modes every one is also written as a record.
+pub fn print_error_summary(errors: &[Error]) {
+    if errors.is_empty() {
+        return;
+    }
+    for error in errors.iter().filter(|_| machine_readable()) {
+        emit(&Record::Error {
+            path: error.path(),
+            message: error.to_string(),
+        });
+    }
+    eprintln!(
+        "{} {}",
+        "❌ ".red(),
+        format!("{} path(s) could not be processed:", errors.len()).red()
+    );
+    for error in errors.iter().take(MAX_LISTED_ERRORS) {
+        eprintln!("{} {}", "•".red(), error);
+    }
+    if errors.len() > MAX_LISTED_ERRORS {
+        eprintln!("  … and {} more", errors.len() - MAX_LISTED_ERRORS);
     }
 }
@@ -0,0 +1,234 @@
+use intelligent_file_deduplicator::filter::FilterOptions;
+use intelligent_file_deduplicator::hashing::HashAlgorithm;
+use intelligent_file_deduplicator::keep_policy::KeepPolicy;
+use intelligent_file_deduplicator::report::{PlannedAction, read_plan, write_json_report};
+use intelligent_file_deduplicator::safe_delete::{DeleteOptions, DuplicateAction, apply_plan};
+use intelligent_file_deduplicator::scanner::scan_directory_with_stats;
+use std::{
+    ffi::OsStr,
+    fs,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{MetadataExt, symlink},
+    },
+    path::Path,
+};
+use tempfile::tempdir;
+
+/// Scans `data` and writes a plan that keeps the first path and deletes the rest.
+fn write_plan(data: &Path, plan_path: &Path) {
+    let scan = scan_directory_with_stats(
+        data.to_str().unwrap(),
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+    );
+    write_json_report(
+        &scan,
+        &KeepPolicy::default(),
+        DuplicateAction::Delete,
+        plan_path,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_apply_executes_plan() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let plan_path = dir.path().join("plan.json");
+    fs::create_dir(&data).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(data.join(name), b"planned").unwrap();
+    }
+
+    write_plan(&data, &plan_path);
+    apply_plan(&read_plan(&plan_path).unwrap(), &DeleteOptions::default()).unwrap();
+
+    assert!(data.join("a.txt").exists());
+    assert!(!data.join("b.txt").exists());
+    assert!(!data.join("c.txt").exists());
+}
+
+#[test]
+fn test_apply_follows_edited_plan() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let plan_path = dir.path().join("plan.json");
+    fs::create_dir(&data).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(data.join(name), b"reviewed").unwrap();
+    }
+
+    write_plan(&data, &plan_path);
+
+    // A reviewer keeps c.txt instead, and decides to leave b.txt alone
+    let mut plan = read_plan(&plan_path).unwrap();
+    let group = &mut plan.duplicates[0];
+    let a = group.keep.clone();
+    group.keep = data.join("c.txt");
+    group
+        .actions
+        .retain(|planned| !planned.path.ends_with("b.txt"));
+    for planned in &mut group.actions {
+        planned.path = a.clone();
+    }
+
+    apply_plan(&plan, &DeleteOptions::default()).unwrap();
+    assert!(!data.join("a.txt").exists());
+    assert!(data.join("b.txt").exists());
+    assert!(data.join("c.txt").exists());
+}
+
+#[test]
+fn test_apply_leaves_hard_links_of_kept_file() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let plan_path = dir.path().join("plan.json");
+    fs::create_dir(&data).unwrap();
+    fs::write(data.join("a.txt"), b"linked").unwrap();
+    fs::hard_link(data.join("a.txt"), data.join("b.txt")).unwrap();
+    fs::write(data.join("c.txt"), b"linked").unwrap();
+
+    write_plan(&data, &plan_path);
+    let mut plan = read_plan(&plan_path).unwrap();
+    let group = &mut plan.duplicates[0];
+    assert_eq!(group.keep, data.join("a.txt"));
+    let planned: Vec<_> = group.actions.iter().map(|p| p.path.clone()).collect();
+    assert_eq!(planned, [data.join("c.txt")]);
+
+    // Even a hand-edited plan must not delete another name of the kept file
+    group.actions.push(PlannedAction {
+        path: data.join("b.txt"),
+        action: DuplicateAction::Delete,
+    });
+    let summary = apply_plan(&plan, &DeleteOptions::default()).unwrap();
+    assert_eq!(summary.processed, 1);
+    assert_eq!(summary.skipped, 1);
+    assert!(data.join("a.txt").exists());
+    assert!(data.join("b.txt").exists());
+    assert!(!data.join("c.txt").exists());
+}
+
+#[test]
+fn test_apply_refuses_files_changed_since_plan() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let plan_path = dir.path().join("plan.json");
+    fs::create_dir(&data).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(data.join(name), b"stable").unwrap();
+    }
+
+    write_plan(&data, &plan_path);
+
+    // b.txt is rewritten with the same length after the plan was written
+    fs::write(data.join("b.txt"), b"edited").unwrap();
+    let later = fs::metadata(data.join("a.txt"))
+        .unwrap()
+        .modified()
+        .unwrap()
+        + std::time::Duration::from_secs(5);
+    fs::File::options()
+        .write(true)
+        .open(data.join("b.txt"))
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+
+    apply_plan(&read_plan(&plan_path).unwrap(), &DeleteOptions::default()).unwrap();
+    assert!(data.join("a.txt").exists());
+    assert!(data.join("b.txt").exists());
+    assert!(!data.join("c.txt").exists());
+}
+
+#[test]
+fn test_plan_round_trips_non_utf8_paths() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let plan_path = dir.path().join("plan.json");
+    fs::create_dir(&data).unwrap();
+
+    let kept = data.join("a.txt");
+    let latin1 = data.join(OsStr::from_bytes(b"caf\xe9.txt"));
+    let backslash = data.join(OsStr::from_bytes(b"back\\x41\xff.txt"));
+    for path in [&kept, &latin1, &backslash] {
+        fs::write(path, b"not utf-8").unwrap();
+    }
+
+    write_plan(&data, &plan_path);
+    let json = fs::read_to_string(&plan_path).unwrap();
+    assert!(json.contains(r#""bytes": "#));
+    assert!(json.contains(r"caf\\xe9.txt"));
+    assert!(json.contains(r"back\\\\x41\\xff.txt"));
+
+    let plan = read_plan(&plan_path).unwrap();
+    let group = &plan.duplicates[0];
+    assert_eq!(group.keep, kept);
+    assert!(group.files.contains(&latin1));
+    assert!(group.files.contains(&backslash));
+
+    apply_plan(&plan, &DeleteOptions::default()).unwrap();
+    assert!(kept.exists());
+    assert!(!latin1.exists());
+    assert!(!backslash.exists());
+}
+
+#[test]
+fn test_apply_refuses_kept_file_replaced_by_symlink() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let plan_path = dir.path().join("plan.json");
+    fs::create_dir(&data).unwrap();
+    for name in ["a.txt", "b.txt"] {
+        fs::write(data.join(name), b"real copy").unwrap();
+    }
+
+    write_plan(&data, &plan_path);
+
+    // The kept a.txt now points at b.txt, the only remaining copy
+    fs::remove_file(data.join("a.txt")).unwrap();
+    symlink("b.txt", data.join("a.txt")).unwrap();
+
+    let summary = apply_plan(&read_plan(&plan_path).unwrap(), &DeleteOptions::default()).unwrap();
+    assert_eq!(summary.processed, 0);
+    assert_eq!(fs::read(data.join("b.txt")).unwrap(), b"real copy");
+}
+
+#[test]
+fn test_apply_link_leaves_no_temporary_files_for_existing_links() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let plan_path = dir.path().join("plan.json");
+    fs::create_dir(&data).unwrap();
+    fs::write(data.join("a.txt"), b"linked").unwrap();
+    fs::hard_link(data.join("a.txt"), data.join("b.txt")).unwrap();
+    fs::write(data.join("c.txt"), b"linked").unwrap();
+
+    let scan = scan_directory_with_stats(
+        data.to_str().unwrap(),
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+    );
+    write_json_report(
+        &scan,
+        &KeepPolicy::default(),
+        DuplicateAction::Link,
+        &plan_path,
+    )
+    .unwrap();
+    let options = DeleteOptions {
+        journal: Some(dir.path().join("journal.jsonl")),
+        ..DeleteOptions::default()
+    };
+    apply_plan(&read_plan(&plan_path).unwrap(), &options).unwrap();
+
+    let mut names: Vec<_> = fs::read_dir(&data)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["a.txt", "b.txt", "c.txt"]);
+    let ino = |name: &str| fs::metadata(data.join(name)).unwrap().ino();
+    assert_eq!(ino("b.txt"), ino("a.txt"));
+    assert_eq!(ino("c.txt"), ino("a.txt"));
+}
@@ -0,0 +1,206 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::tempdir;
+
+fn hashlaser() -> Command {
+    Command::cargo_bin("intelligent-file-deduplicator").unwrap()
+}
+
+#[test]
+fn test_invalid_options_are_rejected_before_deleting() {
+    let dir = tempdir().unwrap();
+    let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+    fs::write(&a, b"same").unwrap();
+    fs::write(&b, b"same").unwrap();
+
+    for bad in [
+        &["--regex", "("][..],
+        &["--min", "ten"],
+        &["--min", "10MB", "--max", "1MB"],
+        &["--bogus"],
+        &["--trash", "--link"],
+    ] {
+        hashlaser()
+            .arg("delete")
+            .arg(dir.path())
+            .args(bad)
+            .args(["--no-cache", "--journal"])
+            .arg(dir.path().join("journal.jsonl"))
+            .assert()
+            .failure();
+        assert!(a.exists() && b.exists(), "{bad:?} deleted a file");
+    }
+}
+
+#[test]
+fn test_size_units_and_help() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), b"same").unwrap();
+    fs::write(dir.path().join("b.txt"), b"same").unwrap();
+
+    hashlaser()
+        .arg("scan")
+        .arg(dir.path())
+        .args(["--min", "1KiB", "--no-cache"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No duplicates found."));
+
+    hashlaser()
+        .args(["delete", "--help"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--reference <DIR>"));
+}
+
+#[test]
+fn test_completions_are_generated() {
+    for shell in ["bash", "zsh", "fish"] {
+        hashlaser()
+            .args(["completions", shell])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("hashlaser"));
+    }
+}
+
+#[test]
+fn test_ndjson_output_is_one_record_per_line() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), b"same").unwrap();
+    fs::write(dir.path().join("b.txt"), b"same").unwrap();
+    fs::write(dir.path().join("c.txt"), b"other").unwrap();
+
+    let output = hashlaser()
+        .args(["--format", "ndjson", "scan", "--no-cache"])
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let records: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    let groups: Vec<_> = records.iter().filter(|r| r["type"] == "group").collect();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0]["files"].as_array().unwrap().len(), 2);
+    assert_eq!(records.last().unwrap()["type"], "scan-summary");
+}
+
+#[test]
+fn test_json_output_for_compare_and_delete() {
+    let dir = tempdir().unwrap();
+    let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+    fs::write(&a, b"same").unwrap();
+    fs::write(&b, b"same").unwrap();
+
+    let output = hashlaser()
+        .args(["compare", "--format", "json"])
+        .args([&a, &b])
+        .output()
+        .unwrap();
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(records[0]["type"], "compare");
+    assert_eq!(records[0]["identical"], true);
+
+    let missing = dir.path().join("missing.txt");
+    let output = hashlaser()
+        .args(["compare", "--format", "json"])
+        .args([&a, &missing])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(records[0]["type"], "error");
+    assert_eq!(records[0]["path"], missing.to_str().unwrap());
+
+    // A directory opens fine but cannot be read; it is the one to blame
+    let output = hashlaser()
+        .args(["compare", "--format", "json"])
+        .arg(&a)
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(records[0]["path"], dir.path().to_str().unwrap());
+
+    let output = hashlaser()
+        .args(["--format", "json", "delete", "--dry-run", "--no-cache"])
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let types: Vec<&str> = records
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["type"].as_str().unwrap())
+        .collect();
+    assert!(types.contains(&"keep") && types.contains(&"would-act"));
+    assert_eq!(types.last(), Some(&"delete-summary"));
+    assert!(a.exists() && b.exists());
+}
+
+#[test]
+fn test_exit_codes() {
+    let dir = tempdir().unwrap();
+    let (a, b, c) = (
+        dir.path().join("a.txt"),
+        dir.path().join("b.txt"),
+        dir.path().join("c.txt"),
+    );
+    fs::write(&a, b"same").unwrap();
+    fs::write(&b, b"same").unwrap();
+    fs::write(&c, b"other").unwrap();
+
+    hashlaser().arg("compare").args([&a, &b]).assert().code(0);
+    hashlaser().arg("compare").args([&a, &c]).assert().code(1);
+    hashlaser()
+        .arg("compare")
+        .arg(&a)
+        .arg(dir.path().join("missing.txt"))
+        .assert()
+        .code(3);
+
+    let scan = |args: &[&str]| {
+        let mut command = hashlaser();
+        command
+            .arg("scan")
+            .arg(dir.path())
+            .arg("--no-cache")
+            .args(args);
+        command
+    };
+    scan(&[]).assert().code(1);
+    scan(&["--min", "1KB"]).assert().code(0);
+    scan(&["--min", "2KB", "--max", "1KB"]).assert().code(2);
+    scan(&["--bogus"]).assert().code(2);
+    scan(&[]).arg(dir.path().join("missing")).assert().code(3);
+}
+
+#[test]
+fn test_dedupe_extents_without_support_fails() {
+    // tmpfs cannot share extents
+    let Ok(dir) = tempfile::tempdir_in("/dev/shm") else {
+        return;
+    };
+    let state = tempdir().unwrap();
+    let journal = state.path().join("journal.jsonl");
+    fs::write(dir.path().join("a.txt"), b"same").unwrap();
+    fs::write(dir.path().join("b.txt"), b"same").unwrap();
+
+    hashlaser()
+        .args(["delete", "--dedupe-extents", "--no-cache", "--journal"])
+        .arg(&journal)
+        .arg(dir.path())
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("Nothing was deduplicated"));
+    assert!(!journal.exists());
+    assert!(dir.path().join("b.txt").exists());
+}
@@ -1,4 +1,7 @@
-use intelligent_file_deduplicator::file_compare::compare_files;
+use intelligent_file_deduplicator::file_compare::{
+    compare_files, compare_files_with, files_identical,
+};
+use intelligent_file_deduplicator::hashing::HashAlgorithm;
 use std::io::Write;
 use tempfile::NamedTempFile;
 
@@ -31,3 +34,39 @@ fn test_compare_different_files() {
     let result = compare_files(path1, path2).unwrap();
     assert!(!result, "Files should be different");
 }
+
+#[test]
+fn test_compare_with_each_algorithm() {
+    let mut file1 = NamedTempFile::new().unwrap();
+    let mut file2 = NamedTempFile::new().unwrap();
+
+    writeln!(file1, "HashLaser is awesome!").unwrap();
+    writeln!(file2, "HashLaser is awesome?").unwrap();
+
+    let path1 = file1.path().to_str().unwrap();
+    let path2 = file2.path().to_str().unwrap();
+
+    for algorithm in [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Xxh3,
+    ] {
+        assert!(compare_files_with(path1, path1, algorithm.hasher()).unwrap());
+        assert!(!compare_files_with(path1, path2, algorithm.hasher()).unwrap());
+    }
+}
+
+#[test]
+fn test_files_identical_byte_by_byte() {
+    let mut file1 = NamedTempFile::new().unwrap();
+    let mut file2 = NamedTempFile::new().unwrap();
+    let mut file3 = NamedTempFile::new().unwrap();
+
+    let content = "x".repeat(20_000);
+    write!(file1, "{content}").unwrap();
+    write!(file2, "{content}").unwrap();
+    write!(file3, "{}y", &content[1..]).unwrap();
+
+    assert!(files_identical(file1.path(), file2.path()).unwrap());
+    assert!(!files_identical(file1.path(), file3.path()).unwrap());
+}
@@ -1,7 +1,29 @@
-use intelligent_file_deduplicator::safe_delete::delete_duplicates;
-use std::{collections::HashMap, fs::File, io::Write};
+use intelligent_file_deduplicator::error::Error;
+use intelligent_file_deduplicator::filter::FilterOptions;
+use intelligent_file_deduplicator::hashing::HashAlgorithm;
+use intelligent_file_deduplicator::keep_policy::{KeepPolicy, KeepRule};
+use intelligent_file_deduplicator::safe_delete::{
+    DeleteOptions, DuplicateAction, delete_duplicates,
+};
+use intelligent_file_deduplicator::scanner::{
+    DuplicateGroup, FileEntry, ScanResult, SymlinkPolicy, scan_directory_with_stats, scan_roots,
+};
+use std::{
+    fs::{self, File},
+    io::Write,
+    os::unix::fs::symlink,
+    path::Path,
+};
 use tempfile::tempdir;
 
+fn scan(dir: &Path) -> ScanResult {
+    scan_directory_with_stats(
+        dir.to_str().unwrap(),
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+    )
+}
+
 #[test]
 fn test_dry_run_deletion() {
     let dir = tempdir().unwrap();
@@ -18,17 +40,15 @@ fn test_dry_run_deletion() {
         .write_all(b"same content")
         .unwrap();
 
-    let mut duplicates = HashMap::new();
-    duplicates.insert(
-        "dummyhash".to_string(),
-        vec![
-            file1.to_string_lossy().to_string(),
-            file2.to_string_lossy().to_string(),
-        ],
-    );
+    let result = scan(dir.path());
+    assert_eq!(result.groups.len(), 1);
 
     // Dry run (no file should be deleted)
-    let result = delete_duplicates(&duplicates, true);
+    let options = DeleteOptions {
+        dry_run: true,
+        ..DeleteOptions::default()
+    };
+    let result = delete_duplicates(&result, &options);
     assert!(result.is_ok());
     assert!(file1.exists());
     assert!(file2.exists());
@@ -50,19 +70,344 @@ fn test_actual_deletion() {
         .write_all(b"duplicate")
         .unwrap();
 
-    let mut duplicates = HashMap::new();
-    duplicates.insert(
-        "hashkey".to_string(),
-        vec![
-            file1.to_string_lossy().to_string(),
-            file2.to_string_lossy().to_string(),
-        ],
-    );
-
-    let result = delete_duplicates(&duplicates, false);
+    let result = delete_duplicates(&scan(dir.path()), &DeleteOptions::default());
     assert!(result.is_ok());
 
     // Only one file should be kept, so at least one must exist
     let remaining = [file1.exists(), file2.exists()];
     assert_eq!(remaining.iter().filter(|&&exists| exists).count(), 1);
 }
+
+#[test]
+fn test_hard_links_of_kept_file_survive() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), b"linked").unwrap();
+    fs::hard_link(dir.path().join("a.txt"), dir.path().join("b.txt")).unwrap();
+    fs::write(dir.path().join("c.txt"), b"linked").unwrap();
+
+    for action in [DuplicateAction::Delete, DuplicateAction::Trash] {
+        let options = DeleteOptions {
+            action,
+            trash_home: Some(dir.path().join("trash")),
+            dry_run: true,
+            ..DeleteOptions::default()
+        };
+        let summary = delete_duplicates(&scan(dir.path()), &options).unwrap();
+        assert_eq!(summary.processed, 1);
+        assert_eq!(summary.skipped, 1);
+    }
+
+    let summary = delete_duplicates(&scan(dir.path()), &DeleteOptions::default()).unwrap();
+    assert_eq!(summary.processed, 1);
+    assert!(dir.path().join("a.txt").exists());
+    assert!(dir.path().join("b.txt").exists());
+    assert!(!dir.path().join("c.txt").exists());
+}
+
+#[test]
+fn test_followed_symlinks_are_never_acted_on() {
+    let dir = tempdir().unwrap();
+    let outside = tempdir().unwrap();
+    let target = outside.path().join("target.txt");
+    let (copy, link) = (
+        dir.path().join("copy.txt"),
+        dir.path().join("a-long-link.txt"),
+    );
+    fs::write(&target, b"only copy outside").unwrap();
+    fs::write(&copy, b"only copy outside").unwrap();
+    symlink(&target, &link).unwrap();
+
+    let filters = FilterOptions {
+        symlinks: SymlinkPolicy::Follow,
+        ..FilterOptions::default()
+    };
+    for keep in [
+        KeepPolicy::default(),
+        KeepPolicy::new(vec![KeepRule::LongestPath]),
+    ] {
+        let scan = scan_directory_with_stats(
+            dir.path().to_str().unwrap(),
+            &filters,
+            HashAlgorithm::Sha256,
+        );
+        let options = DeleteOptions {
+            keep,
+            ..DeleteOptions::default()
+        };
+        let summary = delete_duplicates(&scan, &options).unwrap();
+        assert_eq!((summary.processed, summary.skipped), (0, 1));
+        assert!(target.exists());
+        assert!(copy.exists());
+        assert!(link.is_symlink());
+    }
+}
+
+#[test]
+fn test_file_modified_after_scan_is_skipped() {
+    let dir = tempdir().unwrap();
+
+    let file1 = dir.path().join("file1.txt");
+    let file2 = dir.path().join("file2.txt");
+
+    File::create(&file1)
+        .unwrap()
+        .write_all(b"original")
+        .unwrap();
+    File::create(&file2)
+        .unwrap()
+        .write_all(b"original")
+        .unwrap();
+
+    let result = scan(dir.path());
+
+    // Grow both files after the scan so neither matches its snapshot
+    for path in [&file1, &file2] {
+        File::options()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(b" plus an edit")
+            .unwrap();
+    }
+
+    delete_duplicates(&result, &DeleteOptions::default()).unwrap();
+    assert!(file1.exists());
+    assert!(file2.exists());
+}
+
+#[test]
+fn test_mismatched_contents_are_not_deleted() {
+    let dir = tempdir().unwrap();
+
+    let file1 = dir.path().join("file1.txt");
+    let file2 = dir.path().join("file2.txt");
+
+    File::create(&file1)
+        .unwrap()
+        .write_all(b"content A")
+        .unwrap();
+    File::create(&file2)
+        .unwrap()
+        .write_all(b"content B")
+        .unwrap();
+
+    // A stale or forged hash map claiming the two files are duplicates
+    let result = ScanResult {
+        groups: vec![DuplicateGroup {
+            digest: "dummyhash".to_string(),
+            size: 9,
+            files: vec![
+                FileEntry::capture(&file1).unwrap(),
+                FileEntry::capture(&file2).unwrap(),
+            ],
+        }],
+        ..ScanResult::default()
+    };
+
+    delete_duplicates(&result, &DeleteOptions::default()).unwrap();
+    assert!(file1.exists());
+    assert!(file2.exists());
+}
+
+#[test]
+fn test_trash_action_moves_duplicate_to_trash() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let trash_home = dir.path().join("Trash");
+    fs::create_dir(&data).unwrap();
+
+    let file1 = data.join("file1.txt");
+    let file2 = data.join("file2.txt");
+    File::create(&file1)
+        .unwrap()
+        .write_all(b"trash me")
+        .unwrap();
+    File::create(&file2)
+        .unwrap()
+        .write_all(b"trash me")
+        .unwrap();
+
+    let options = DeleteOptions {
+        action: DuplicateAction::Trash,
+        trash_home: Some(trash_home.clone()),
+        ..DeleteOptions::default()
+    };
+    delete_duplicates(&scan(&data), &options).unwrap();
+
+    // Exactly one copy left in place, the other now lives in the trash
+    assert_eq!(
+        [file1.exists(), file2.exists()]
+            .iter()
+            .filter(|&&e| e)
+            .count(),
+        1
+    );
+    let trashed: Vec<_> = fs::read_dir(trash_home.join("files")).unwrap().collect();
+    let infos: Vec<_> = fs::read_dir(trash_home.join("info")).unwrap().collect();
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(infos.len(), 1);
+}
+
+#[test]
+fn test_link_action_replaces_duplicates_with_hard_links() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempdir().unwrap();
+    let paths: Vec<_> = ["a.txt", "b.txt", "c.txt"]
+        .iter()
+        .map(|name| dir.path().join(name))
+        .collect();
+    for path in &paths {
+        File::create(path).unwrap().write_all(b"link me").unwrap();
+    }
+
+    let options = DeleteOptions {
+        action: DuplicateAction::Link,
+        ..DeleteOptions::default()
+    };
+    delete_duplicates(&scan(dir.path()), &options).unwrap();
+
+    // Every path still exists and they all share one inode
+    let inodes: Vec<_> = paths
+        .iter()
+        .map(|path| fs::metadata(path).unwrap().ino())
+        .collect();
+    assert!(inodes.iter().all(|&ino| ino == inodes[0]));
+    assert_eq!(fs::metadata(&paths[0]).unwrap().nlink(), 3);
+    assert_eq!(fs::read(&paths[2]).unwrap(), b"link me");
+
+    // No temporary link names are left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+}
+
+#[test]
+fn test_link_action_counts_copies_on_other_filesystems_as_skipped() {
+    use std::os::unix::fs::MetadataExt;
+
+    // Needs a second filesystem; /dev/shm is a tmpfs on most Linux systems
+    let dir = tempdir().unwrap();
+    let Ok(other) = tempfile::tempdir_in("/dev/shm") else {
+        return;
+    };
+    let dev = |path: &Path| fs::metadata(path).unwrap().dev();
+    if dev(dir.path()) == dev(other.path()) {
+        return;
+    }
+    let (a, b) = (dir.path().join("a.txt"), other.path().join("b.txt"));
+    fs::write(&a, b"across filesystems").unwrap();
+    fs::write(&b, b"across filesystems").unwrap();
+
+    let scan = scan_roots(
+        &[dir.path(), other.path()],
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+        None,
+    );
+    let options = DeleteOptions {
+        action: DuplicateAction::Link,
+        ..DeleteOptions::default()
+    };
+    let summary = delete_duplicates(&scan, &options).unwrap();
+    assert_eq!((summary.processed, summary.skipped), (0, 1));
+    assert_ne!(dev(&a), dev(&b));
+}
+
+#[test]
+fn test_summary_counts_skipped_and_failed_files() {
+    let dir = tempdir().unwrap();
+    let paths: Vec<_> = ["a.txt", "b.txt", "c.txt"]
+        .iter()
+        .map(|name| dir.path().join(name))
+        .collect();
+    for path in &paths {
+        fs::write(path, b"summarize me").unwrap();
+    }
+
+    let result = scan(dir.path());
+
+    // After the scan, b.txt disappears and c.txt grows
+    fs::remove_file(&paths[1]).unwrap();
+    fs::write(&paths[2], b"summarize me, edited").unwrap();
+
+    let summary = delete_duplicates(&result, &DeleteOptions::default()).unwrap();
+    assert_eq!(summary.processed, 0);
+    assert_eq!(summary.skipped, 1);
+    assert_eq!(summary.errors.len(), 1);
+    assert!(matches!(&summary.errors[0], Error::Vanished { path } if *path == paths[1]));
+    assert!(paths[0].exists());
+}
+
+#[test]
+fn test_reference_files_are_kept_and_never_touched() {
+    let dir = tempdir().unwrap();
+    let downloads = dir.path().join("a-downloads");
+    let archive = dir.path().join("z-archive");
+    fs::create_dir_all(&downloads).unwrap();
+    fs::create_dir_all(&archive).unwrap();
+
+    // One photo in both places, twice in the archive, and two copies only in the archive
+    for path in [
+        downloads.join("photo.jpg"),
+        archive.join("photo.jpg"),
+        archive.join("photo copy.jpg"),
+    ] {
+        fs::write(path, b"canonical photo").unwrap();
+    }
+    for name in ["notes.txt", "notes-old.txt"] {
+        fs::write(archive.join(name), b"archive only").unwrap();
+    }
+
+    let scan = scan_roots(
+        &[&downloads, &archive],
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+        None,
+    );
+    assert_eq!(scan.groups.len(), 2);
+
+    // The default policy would keep the download, which sorts first
+    let options = DeleteOptions {
+        reference: vec![archive.clone()],
+        ..DeleteOptions::default()
+    };
+    let summary = delete_duplicates(&scan, &options).unwrap();
+
+    assert_eq!(summary.processed, 1);
+    assert!(!downloads.join("photo.jpg").exists());
+    for name in ["photo.jpg", "photo copy.jpg", "notes.txt", "notes-old.txt"] {
+        assert!(archive.join(name).exists(), "{name} was touched");
+    }
+}
+
+#[test]
+fn test_refused_group_counts_only_files_it_would_act_on() {
+    let dir = tempdir().unwrap();
+    let downloads = dir.path().join("a-downloads");
+    let archive = dir.path().join("z-archive");
+    fs::create_dir_all(&downloads).unwrap();
+    fs::create_dir_all(&archive).unwrap();
+    for path in [
+        downloads.join("photo.jpg"),
+        archive.join("photo.jpg"),
+        archive.join("photo copy.jpg"),
+    ] {
+        fs::write(path, b"canonical photo").unwrap();
+    }
+
+    let scan = scan_roots(
+        &[&downloads, &archive],
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+        None,
+    );
+    // The kept archive copy changes, so the download must stay
+    fs::write(archive.join("photo copy.jpg"), b"canonical photo, edited").unwrap();
+
+    let options = DeleteOptions {
+        reference: vec![archive.clone()],
+        ..DeleteOptions::default()
+    };
+    let summary = delete_duplicates(&scan, &options).unwrap();
+    assert_eq!((summary.processed, summary.skipped), (0, 1));
+    assert!(downloads.join("photo.jpg").exists());
+}
@@ -1,4 +1,4 @@
-use intelligent_file_deduplicator::filter::FilterOptions;
+use intelligent_file_deduplicator::filter::{FilterOptions, compile_globs, parse_size};
 use regex::Regex;
 use std::{
     fs::{self, File},
@@ -23,6 +23,7 @@ fn test_filter_options_match() {
         max_size: Some(file_size + 10),
         extensions: Some(vec!["txt".to_string()]),
         regex: Some(Regex::new(r".*log.*").unwrap()),
+        ..FilterOptions::default()
     };
 
     assert!(options.matches(Path::new(&file_path)));
@@ -41,7 +42,53 @@ fn test_filter_excludes_large_file() {
         max_size: Some(5000),
         extensions: None,
         regex: None,
+        ..FilterOptions::default()
     };
 
     assert!(!options.matches(Path::new(&file_path)));
 }
+
+#[test]
+fn test_exclude_globs_match_name_or_relative_path() {
+    let options = FilterOptions {
+        exclude: compile_globs(&["*.bak"]).unwrap(),
+        exclude_dirs: compile_globs(&["vendor/*/tests"]).unwrap(),
+        ..FilterOptions::default()
+    };
+    let root = Path::new("/data");
+    let excludes = |path: &str, is_dir| {
+        let path = Path::new(path);
+        options.excludes(path, path.strip_prefix(root).ok(), is_dir)
+    };
+
+    assert!(excludes("/data/deep/file.bak", false));
+    assert!(excludes("/data/vendor/lib/tests", true));
+    assert!(!excludes("/data/vendor/lib/tests", false));
+    assert!(!excludes("/data/file.txt", false));
+    assert!(compile_globs(&["[unclosed"]).is_err());
+    assert!(compile_globs(&[]).unwrap().is_none());
+}
+
+#[test]
+fn test_parse_size_accepts_human_units() {
+    assert_eq!(parse_size("4096"), Ok(4096));
+    assert_eq!(parse_size("512B"), Ok(512));
+    assert_eq!(parse_size("10MB"), Ok(10_000_000));
+    assert_eq!(parse_size("10mb"), Ok(10_000_000));
+    assert_eq!(parse_size("4k"), Ok(4_000));
+    assert_eq!(parse_size("4KiB"), Ok(4096));
+    assert_eq!(parse_size("1.5GiB"), Ok(3 << 29));
+    assert_eq!(parse_size("2 TB"), Ok(2_000_000_000_000));
+
+    for invalid in [
+        "",
+        "MB",
+        "ten",
+        "10XB",
+        "-1",
+        "1.2.3k",
+        "99999999999999999999TB",
+    ] {
+        assert!(parse_size(invalid).is_err(), "{invalid} was accepted");
+    }
+}
@@ -0,0 +1,127 @@
+use intelligent_file_deduplicator::filter::FilterOptions;
+use intelligent_file_deduplicator::hash_cache::HashCache;
+use intelligent_file_deduplicator::hashing::HashAlgorithm;
+use intelligent_file_deduplicator::scanner::{ScanResult, scan_directory_with_cache};
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tempfile::tempdir;
+
+/// Writes `contents` to `path` with an mtime old enough to be cached.
+fn write_settled(path: &Path, contents: &[u8]) {
+    fs::write(path, contents).unwrap();
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(60))
+        .unwrap();
+}
+
+/// The groups of `scan` as a set, so comparisons don't depend on discovery order.
+fn group_set(scan: &ScanResult) -> BTreeSet<(String, BTreeSet<PathBuf>)> {
+    scan.groups
+        .iter()
+        .map(|group| {
+            let paths = group.paths().map(Path::to_path_buf).collect();
+            (group.digest.clone(), paths)
+        })
+        .collect()
+}
+
+#[test]
+fn test_unchanged_files_are_served_from_cache() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let cache_path = dir.path().join("cache/hashes.json");
+    fs::create_dir(&data).unwrap();
+    write_settled(&data.join("a.txt"), b"cached");
+    write_settled(&data.join("b.txt"), b"cached");
+
+    let root = data.to_str().unwrap();
+    let first = HashCache::open(&cache_path).unwrap();
+    let result = scan_directory_with_cache(
+        root,
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+        &first,
+    );
+    assert_eq!(result.groups.len(), 1);
+    assert_eq!((first.hits(), first.misses()), (0, 2));
+    first.save().unwrap();
+
+    // A fresh process reloads the cache and re-reads nothing
+    let second = HashCache::open(&cache_path).unwrap();
+    let again = scan_directory_with_cache(
+        root,
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+        &second,
+    );
+    assert_eq!(group_set(&again), group_set(&result));
+    assert_eq!((second.hits(), second.misses()), (2, 0));
+
+    // Another algorithm has its own entries
+    let blake3 = scan_directory_with_cache(
+        root,
+        &FilterOptions::default(),
+        HashAlgorithm::Blake3,
+        &second,
+    );
+    assert_eq!(blake3.groups.len(), 1);
+    assert_eq!(second.misses(), 2);
+}
+
+#[test]
+fn test_changed_file_is_rehashed() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    write_settled(&file, b"before");
+
+    let cache = HashCache::new(dir.path().join("hashes.json"));
+    let before = cache.hash_file(&file, HashAlgorithm::Sha256).unwrap();
+
+    // Same size, different contents and mtime
+    write_settled(&file, b"after!");
+    File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(30))
+        .unwrap();
+
+    let after = cache.hash_file(&file, HashAlgorithm::Sha256).unwrap();
+    assert_ne!(before, after);
+    assert_eq!(cache.misses(), 2);
+}
+
+#[test]
+fn test_prune_drops_missing_and_changed_files() {
+    let dir = tempdir().unwrap();
+    let kept = dir.path().join("kept.txt");
+    let removed = dir.path().join("removed.txt");
+    let changed = dir.path().join("changed.txt");
+    for path in [&kept, &removed, &changed] {
+        write_settled(path, b"prune me");
+    }
+
+    let cache = HashCache::new(dir.path().join("hashes.json"));
+    for path in [&kept, &removed, &changed] {
+        cache.hash_file(path, HashAlgorithm::Xxh3).unwrap();
+    }
+    fs::remove_file(&removed).unwrap();
+    fs::write(&changed, b"changed contents").unwrap();
+
+    assert_eq!(cache.prune(), 2);
+    assert_eq!(cache.stats().entries, 1);
+    assert_eq!(
+        cache.stats().by_algorithm,
+        [(HashAlgorithm::Xxh3, 1)].to_vec()
+    );
+
+    cache.clear();
+    assert_eq!(cache.stats().entries, 0);
+}
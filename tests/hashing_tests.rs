@@ -1,4 +1,4 @@
-use intelligent_file_deduplicator::hashing::hash_file;
+use intelligent_file_deduplicator::hashing::{HashAlgorithm, hash_file, hash_file_with};
 use std::io::Write;
 use tempfile::NamedTempFile;
 
@@ -13,3 +13,34 @@ fn test_hash_consistency() {
 
     assert_eq!(hash1, hash2);
 }
+
+#[test]
+fn test_known_digests_per_algorithm() {
+    let mut temp = NamedTempFile::new().unwrap();
+    write!(temp, "abc").unwrap();
+    let path = temp.path().to_str().unwrap();
+
+    let sha256 = hash_file_with(path, HashAlgorithm::Sha256.hasher()).unwrap();
+    let blake3 = hash_file_with(path, HashAlgorithm::Blake3.hasher()).unwrap();
+    let xxh3 = hash_file_with(path, HashAlgorithm::Xxh3.hasher()).unwrap();
+
+    assert_eq!(
+        sha256,
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        blake3,
+        "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    );
+    assert_eq!(xxh3.len(), 32);
+    assert_eq!(sha256, hash_file(path).unwrap());
+}
+
+#[test]
+fn test_parse_hash_algorithm() {
+    assert_eq!("blake3".parse(), Ok(HashAlgorithm::Blake3));
+    assert_eq!("SHA256".parse(), Ok(HashAlgorithm::Sha256));
+    assert_eq!("xxh3".parse(), Ok(HashAlgorithm::Xxh3));
+    assert!("md5".parse::<HashAlgorithm>().is_err());
+    assert_eq!(HashAlgorithm::Xxh3.to_string(), "xxh3");
+}
@@ -0,0 +1,129 @@
+use intelligent_file_deduplicator::filter::FilterOptions;
+use intelligent_file_deduplicator::hashing::HashAlgorithm;
+use intelligent_file_deduplicator::journal::{read_journal, restore_journal};
+use intelligent_file_deduplicator::safe_delete::{
+    DeleteOptions, DuplicateAction, delete_duplicates,
+};
+use intelligent_file_deduplicator::scanner::scan_directory_with_stats;
+use std::{
+    fs,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+};
+use tempfile::tempdir;
+
+/// Runs `action` over `data` with a journal at `journal`.
+fn run(data: &Path, journal: &Path, action: DuplicateAction, trash_home: Option<&Path>) {
+    let scan = scan_directory_with_stats(
+        data.to_str().unwrap(),
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+    );
+    let options = DeleteOptions {
+        action,
+        trash_home: trash_home.map(Path::to_path_buf),
+        journal: Some(journal.to_path_buf()),
+        ..DeleteOptions::default()
+    };
+    delete_duplicates(&scan, &options).unwrap();
+}
+
+#[test]
+fn test_restore_deleted_file_from_kept_copy() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let journal = dir.path().join("undo.jsonl");
+    fs::create_dir(&data).unwrap();
+
+    let kept = data.join("a.txt");
+    let deleted = data.join("b.txt");
+    fs::write(&kept, b"restore me").unwrap();
+    fs::write(&deleted, b"restore me").unwrap();
+    fs::set_permissions(&deleted, fs::Permissions::from_mode(0o640)).unwrap();
+
+    run(&data, &journal, DuplicateAction::Delete, None);
+    assert!(!deleted.exists());
+
+    let entries = read_journal(&journal).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, DuplicateAction::Delete);
+    assert_eq!(entries[0].mode, Some(0o640));
+
+    let summary = restore_journal(&journal).unwrap();
+    assert_eq!(summary.restored.len(), 1);
+    assert!(summary.unrestorable.is_empty());
+    assert_eq!(fs::read(&deleted).unwrap(), b"restore me");
+    assert_eq!(fs::metadata(&deleted).unwrap().mode() & 0o777, 0o640);
+}
+
+#[test]
+fn test_restore_moves_trashed_file_back() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let trash_home = dir.path().join("Trash");
+    let journal = dir.path().join("undo.jsonl");
+    fs::create_dir(&data).unwrap();
+
+    let kept = data.join("a.txt");
+    let trashed = data.join("b.txt");
+    fs::write(&kept, b"trash me").unwrap();
+    fs::write(&trashed, b"trash me").unwrap();
+
+    run(&data, &journal, DuplicateAction::Trash, Some(&trash_home));
+    assert!(!trashed.exists());
+
+    let summary = restore_journal(&journal).unwrap();
+    assert_eq!(summary.restored.len(), 1);
+    assert_eq!(fs::read(&trashed).unwrap(), b"trash me");
+
+    // The trash is left empty, .trashinfo included
+    assert_eq!(fs::read_dir(trash_home.join("files")).unwrap().count(), 0);
+    assert_eq!(fs::read_dir(trash_home.join("info")).unwrap().count(), 0);
+}
+
+#[test]
+fn test_restore_expands_hard_links_into_copies() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let journal = dir.path().join("undo.jsonl");
+    fs::create_dir(&data).unwrap();
+
+    let kept = data.join("a.txt");
+    let linked = data.join("b.txt");
+    fs::write(&kept, b"link me").unwrap();
+    fs::write(&linked, b"link me").unwrap();
+
+    run(&data, &journal, DuplicateAction::Link, None);
+    assert_eq!(fs::metadata(&kept).unwrap().nlink(), 2);
+
+    let summary = restore_journal(&journal).unwrap();
+    assert_eq!(summary.restored.len(), 1);
+    assert_eq!(fs::metadata(&kept).unwrap().nlink(), 1);
+    assert_ne!(
+        fs::metadata(&kept).unwrap().ino(),
+        fs::metadata(&linked).unwrap().ino()
+    );
+    assert_eq!(fs::read(&linked).unwrap(), b"link me");
+}
+
+#[test]
+fn test_restore_reports_file_whose_kept_copy_changed() {
+    let dir = tempdir().unwrap();
+    let data = dir.path().join("data");
+    let journal = dir.path().join("undo.jsonl");
+    fs::create_dir(&data).unwrap();
+
+    let kept = data.join("a.txt");
+    let deleted = data.join("b.txt");
+    fs::write(&kept, b"original").unwrap();
+    fs::write(&deleted, b"original").unwrap();
+
+    run(&data, &journal, DuplicateAction::Delete, None);
+    fs::write(&kept, b"edited afterwards").unwrap();
+
+    let summary = restore_journal(&journal).unwrap();
+    assert!(summary.restored.is_empty());
+    assert_eq!(summary.unrestorable.len(), 1);
+    assert!(summary.unrestorable[0].1.contains("changed"));
+    assert!(!deleted.exists());
+}
@@ -0,0 +1,76 @@
+use intelligent_file_deduplicator::keep_policy::{KeepPolicy, KeepRule};
+use intelligent_file_deduplicator::scanner::FileEntry;
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+fn group(entries: &[(&str, u64)]) -> Vec<FileEntry> {
+    entries
+        .iter()
+        .map(|(path, secs)| FileEntry {
+            path: PathBuf::from(path),
+            size: 1,
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(*secs)),
+            dev: 0,
+            ino: 0,
+            mode: 0o100644,
+            root: 0,
+        })
+        .collect()
+}
+
+fn kept(policy: &str, files: &[FileEntry]) -> String {
+    let policy: KeepPolicy = policy.parse().unwrap();
+    files[policy.choose(files).index]
+        .path
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn test_single_rules() {
+    let files = group(&[
+        ("/data/b/copy.txt", 300),
+        ("/archive/a.txt", 200),
+        ("/data/zz/deeper/copy.txt", 100),
+    ]);
+
+    assert_eq!(kept("oldest", &files), "/data/zz/deeper/copy.txt");
+    assert_eq!(kept("newest", &files), "/data/b/copy.txt");
+    assert_eq!(kept("shortest", &files), "/archive/a.txt");
+    assert_eq!(kept("longest", &files), "/data/zz/deeper/copy.txt");
+    assert_eq!(kept("first", &files), "/archive/a.txt");
+    assert_eq!(kept("under:/data/b", &files), "/data/b/copy.txt");
+}
+
+#[test]
+fn test_rules_combine_as_tie_breakers() {
+    let files = group(&[
+        ("/data/long/name.txt", 100),
+        ("/data/x.txt", 100),
+        ("/other/y.txt", 50),
+    ]);
+
+    let policy: KeepPolicy = "under:/data,oldest,shortest".parse().unwrap();
+    let choice = policy.choose(&files);
+
+    // Both /data files tie on directory and mtime, so path length decides
+    assert_eq!(files[choice.index].path, PathBuf::from("/data/x.txt"));
+    assert_eq!(choice.reason, "shortest path");
+}
+
+#[test]
+fn test_policy_parsing_and_display() {
+    let policy: KeepPolicy = "oldest, under:/srv/master".parse().unwrap();
+    assert_eq!(
+        policy.rules(),
+        [
+            KeepRule::Oldest,
+            KeepRule::Under(PathBuf::from("/srv/master"))
+        ]
+    );
+    assert_eq!(policy.to_string(), "oldest,under:/srv/master");
+    assert!("biggest".parse::<KeepPolicy>().is_err());
+    assert!("under:".parse::<KeepPolicy>().is_err());
+}
@@ -0,0 +1,68 @@
+use intelligent_file_deduplicator::reflink::dedupe_file_extents;
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tempfile::{tempdir, tempdir_in};
+
+fn write_pair(dir: &Path, content: &[u8]) -> (PathBuf, PathBuf) {
+    let src = dir.join("src.bin");
+    let dest = dir.join("dest.bin");
+    File::create(&src).unwrap().write_all(content).unwrap();
+    File::create(&dest).unwrap().write_all(content).unwrap();
+    (src, dest)
+}
+
+#[test]
+fn test_dedupe_reports_unsupported_filesystem() {
+    let dir = tempdir().unwrap();
+    let content = vec![7u8; 64 * 1024];
+    let (src, dest) = write_pair(dir.path(), &content);
+
+    // ext4 and tmpfs must say they are unsupported; a CoW temp dir may just succeed
+    match dedupe_file_extents(&src, &dest) {
+        Ok(bytes) => assert_eq!(bytes, content.len() as u64),
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::Unsupported, "{e}"),
+    }
+    assert_eq!(fs::read(&dest).unwrap(), content);
+}
+
+/// Runs against a btrfs or XFS mount, e.g. a loopback image:
+///
+/// ```text
+/// truncate -s 256M /tmp/btrfs.img && mkfs.btrfs /tmp/btrfs.img
+/// sudo mount -o loop /tmp/btrfs.img /mnt/cow && sudo chown $USER /mnt/cow
+/// HASHLASER_REFLINK_DIR=/mnt/cow cargo test --test reflink_tests
+/// ```
+#[test]
+fn test_dedupe_on_reflink_filesystem() {
+    let Some(root) = env::var_os("HASHLASER_REFLINK_DIR") else {
+        return;
+    };
+    let dir = tempdir_in(root).unwrap();
+    let content: Vec<u8> = (0..(20 * 1024 * 1024u32))
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let (src, dest) = write_pair(dir.path(), &content);
+
+    let bytes = dedupe_file_extents(&src, &dest).unwrap();
+    assert_eq!(bytes, content.len() as u64);
+    assert_eq!(fs::read(&dest).unwrap(), content);
+
+    // Contents that differ are refused by the kernel
+    let other = dir.path().join("other.bin");
+    File::create(&other)
+        .unwrap()
+        .write_all(&content[1..])
+        .unwrap();
+    File::options()
+        .append(true)
+        .open(&other)
+        .unwrap()
+        .write_all(b"x")
+        .unwrap();
+    let err = dedupe_file_extents(&src, &other).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
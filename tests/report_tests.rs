@@ -1,26 +1,233 @@
-use intelligent_file_deduplicator::report::write_json_report;
-use std::collections::HashMap;
+use intelligent_file_deduplicator::filter::{FilterOptions, compile_globs};
+use intelligent_file_deduplicator::hashing::HashAlgorithm;
+use intelligent_file_deduplicator::keep_policy::KeepPolicy;
+use intelligent_file_deduplicator::report::{
+    Plan, REPORT_SCHEMA_VERSION, ReportFormat, read_plan, write_json_report, write_report,
+};
+use intelligent_file_deduplicator::safe_delete::DuplicateAction;
+use intelligent_file_deduplicator::scanner::{DuplicateGroup, FileEntry, ScanResult};
 use std::fs;
+use std::path::{Path, PathBuf};
 use tempfile::tempdir;
 
+/// A group of files that need not exist, as the report never touches them.
+fn group(digest: &str, paths: &[&str]) -> DuplicateGroup {
+    let files = paths
+        .iter()
+        .zip(1..)
+        .map(|(path, ino)| FileEntry {
+            path: PathBuf::from(path),
+            size: 1,
+            modified: None,
+            dev: 0,
+            ino,
+            mode: 0o100644,
+            root: 0,
+        })
+        .collect();
+    DuplicateGroup {
+        digest: digest.to_string(),
+        size: 1,
+        files,
+    }
+}
+
 #[test]
 fn test_json_report_generation() {
-    let mut duplicates = HashMap::new();
-    duplicates.insert(
-        "dummyhash123".to_string(),
-        vec![
-            "path/to/file1.txt".to_string(),
-            "path/to/file2.txt".to_string(),
-        ],
-    );
+    let scan = ScanResult {
+        groups: vec![group(
+            "dummyhash123",
+            &["path/to/file2.txt", "path/to/file1.txt"],
+        )],
+        algorithm: HashAlgorithm::Blake3,
+        ..ScanResult::default()
+    };
 
     let temp = tempdir().unwrap();
     let report_path = temp.path().join("report.json");
 
-    write_json_report(&duplicates, &report_path).expect("Failed to write report");
+    write_json_report(
+        &scan,
+        &KeepPolicy::default(),
+        DuplicateAction::Delete,
+        &report_path,
+    )
+    .expect("Failed to write report");
 
     let contents = fs::read_to_string(&report_path).expect("Failed to read report");
     assert!(contents.contains("dummyhash123"));
     assert!(contents.contains("file1.txt"));
     assert!(contents.contains("file2.txt"));
+    assert!(contents.contains(r#""algorithm": "blake3""#));
+    assert!(contents.contains(r#""keep_policy": "first""#));
+    assert!(contents.contains(r#""keep": "path/to/file1.txt""#));
+    assert!(contents.contains(r#""keep_reason": "first path in lexicographic order""#));
+}
+
+#[test]
+fn test_report_reads_back_as_plan() {
+    let scan = ScanResult {
+        groups: vec![group("dummyhash123", &["b.txt", "a.txt", "c.txt"])],
+        ..ScanResult::default()
+    };
+
+    let temp = tempdir().unwrap();
+    let report_path = temp.path().join("plan.json");
+    write_json_report(
+        &scan,
+        &KeepPolicy::default(),
+        DuplicateAction::Trash,
+        &report_path,
+    )
+    .unwrap();
+
+    let plan = read_plan(&report_path).unwrap();
+    assert_eq!(plan.algorithm, HashAlgorithm::Sha256);
+    assert_eq!(plan.duplicates.len(), 1);
+
+    let group = &plan.duplicates[0];
+    assert_eq!(group.keep, Path::new("a.txt"));
+    let actions: Vec<_> = group
+        .actions
+        .iter()
+        .map(|planned| (planned.path.to_str().unwrap(), planned.action))
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            ("b.txt", DuplicateAction::Trash),
+            ("c.txt", DuplicateAction::Trash)
+        ]
+    );
+}
+
+#[test]
+fn test_report_format_from_extension() {
+    for (name, format) in [
+        ("out.json", Some(ReportFormat::Json)),
+        ("out.CSV", Some(ReportFormat::Csv)),
+        ("out.md", Some(ReportFormat::Markdown)),
+        ("out.htm", Some(ReportFormat::Html)),
+        ("out.txt", None),
+        ("out", None),
+    ] {
+        assert_eq!(
+            ReportFormat::from_extension(Path::new(name)),
+            format,
+            "{name}"
+        );
+    }
+    assert_eq!("md".parse(), Ok(ReportFormat::Markdown));
+    assert!("xml".parse::<ReportFormat>().is_err());
+}
+
+#[test]
+fn test_csv_markdown_and_html_reports() {
+    let scan = ScanResult {
+        groups: vec![
+            group("hash1", &["a/keep.txt", "b/dup.txt"]),
+            group("hash2", &["a/x<y>.txt", "c/x|y.txt"]),
+        ],
+        ..ScanResult::default()
+    };
+    let temp = tempdir().unwrap();
+    let write = |format| {
+        let path = temp.path().join("report");
+        write_report(
+            &scan,
+            &KeepPolicy::default(),
+            DuplicateAction::Trash,
+            format,
+            &path,
+        )
+        .unwrap();
+        fs::read_to_string(&path).unwrap()
+    };
+
+    let csv = write(ReportFormat::Csv);
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(rows[0], "group,hash,size,path,modified,action");
+    assert_eq!(rows[1], "1,hash1,1,a/keep.txt,,keep");
+    assert_eq!(rows[2], "1,hash1,1,b/dup.txt,,trash");
+    assert_eq!(rows.len(), 5);
+
+    let markdown = write(ReportFormat::Markdown);
+    assert!(markdown.contains("- **Groups:** 2"));
+    assert!(markdown.contains("| trash | b/dup.txt |  |"));
+    assert!(markdown.contains(r"c/x\|y.txt"));
+
+    let html = write(ReportFormat::Html);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("a/x&lt;y&gt;.txt"));
+    assert!(html.contains("<code>b</code>"));
+    assert!(html.contains("data-reclaimable="));
+    assert!(html.contains("data-key=\"hash\""));
+    assert!(html.contains("data-hash=\"hash2\""));
+}
+
+#[test]
+fn test_report_is_sorted_and_records_its_origin() {
+    // Files of distinct inodes, listed out of order
+    let sized = |digest: &str, size: u64, paths: &[&str]| {
+        let mut group = group(digest, paths);
+        group.size = size;
+        for (ino, file) in group.files.iter_mut().enumerate() {
+            file.size = size;
+            file.ino = ino as u64;
+        }
+        group
+    };
+    let filters = FilterOptions {
+        min_size: Some(1),
+        exclude: compile_globs(&["*.tmp"]).unwrap(),
+        ..FilterOptions::default()
+    };
+    let scan = ScanResult {
+        roots: vec![PathBuf::from("/data")],
+        groups: vec![
+            sized("aaa", 10, &["z.txt", "y.txt"]),
+            sized("bbb", 500, &["m.txt", "b.txt", "k.txt"]),
+            sized("ccc", 10, &["d.txt", "c.txt"]),
+        ],
+        filters: filters.settings(),
+        ..ScanResult::default()
+    };
+
+    let plan = Plan::from_scan(&scan, &KeepPolicy::default(), DuplicateAction::Delete);
+    let hashes: Vec<&str> = plan.duplicates.iter().map(|g| g.hash.as_str()).collect();
+    assert_eq!(hashes, ["bbb", "aaa", "ccc"]);
+    let files: Vec<&str> = plan.duplicates[0]
+        .files
+        .iter()
+        .map(|path| path.to_str().unwrap())
+        .collect();
+    assert_eq!(files, ["b.txt", "k.txt", "m.txt"]);
+
+    assert_eq!(plan.schema_version, REPORT_SCHEMA_VERSION);
+    assert_eq!(plan.tool_version, env!("CARGO_PKG_VERSION"));
+    assert!(plan.generated_at.ends_with('Z'));
+    assert_eq!(plan.roots, [PathBuf::from("/data")]);
+    assert_eq!(plan.filters.min_size, Some(1));
+    assert_eq!(plan.filters.exclude, ["*.tmp"]);
+}
+
+#[test]
+fn test_newer_report_schema_is_rejected() {
+    let temp = tempdir().unwrap();
+    let path = temp.path().join("plan.json");
+    fs::write(
+        &path,
+        r#"{"schema_version": 99, "algorithm": "sha256", "keep_policy": "first", "duplicates": []}"#,
+    )
+    .unwrap();
+    let error = read_plan(&path).unwrap_err();
+    assert!(error.to_string().contains("newer"));
+
+    // Reports written before versioning still read
+    fs::write(
+        &path,
+        r#"{"algorithm": "sha256", "keep_policy": "first", "duplicates": []}"#,
+    )
+    .unwrap();
+    assert_eq!(read_plan(&path).unwrap().schema_version, 0);
 }
@@ -1,7 +1,20 @@
-use intelligent_file_deduplicator::filter::FilterOptions;
-use intelligent_file_deduplicator::scanner::scan_directory_for_duplicates;
+use intelligent_file_deduplicator::error::Error;
+use intelligent_file_deduplicator::filter::{FilterOptions, compile_globs};
+use intelligent_file_deduplicator::hash_cache::HashCache;
+use intelligent_file_deduplicator::hashing::HashAlgorithm;
+use intelligent_file_deduplicator::scanner::{
+    CancellationToken, ScanEvent, ScanResult, Scanner, SymlinkPolicy,
+    scan_directory_for_duplicates, scan_directory_with_cache, scan_directory_with_stats,
+    scan_roots,
+};
 
-use std::{fs::File, io::Write, path::PathBuf};
+use std::{
+    fs::{self, File},
+    io::Write,
+    os::unix::fs::{MetadataExt, symlink},
+    path::Path,
+    sync::Mutex,
+};
 use tempfile::tempdir;
 
 #[test]
@@ -20,28 +33,499 @@ fn test_duplicate_detection() {
     writeln!(file2, "HashLaser").unwrap();
     writeln!(file3, "Different content").unwrap();
 
-    let duplicates =
+    let groups =
         scan_directory_for_duplicates(dir.path().to_str().unwrap(), &FilterOptions::default());
 
-    let mut found = false;
-    for (_hash, files) in &duplicates {
-        if files.len() > 1 {
-            found = true;
-            let file_names: Vec<_> = files
-                .iter()
-                .map(|f| {
-                    PathBuf::from(f)
-                        .file_name()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_string()
-                })
-                .collect();
-            assert!(file_names.contains(&"file1.txt".to_string()));
-            assert!(file_names.contains(&"file2.txt".to_string()));
-        }
+    assert_eq!(
+        groups.len(),
+        1,
+        "Should detect duplicates between file1 and file2"
+    );
+    let file_names: Vec<_> = groups[0]
+        .paths()
+        .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(file_names, ["file1.txt", "file2.txt"]);
+}
+
+#[test]
+fn test_stages_remove_unique_candidates() {
+    let dir = tempdir().unwrap();
+
+    // Same size and content: true duplicates
+    File::create(dir.path().join("a.txt"))
+        .unwrap()
+        .write_all(b"same bytes")
+        .unwrap();
+    File::create(dir.path().join("b.txt"))
+        .unwrap()
+        .write_all(b"same bytes")
+        .unwrap();
+    // Same size, different content: ruled out by the sample stage
+    File::create(dir.path().join("c.txt"))
+        .unwrap()
+        .write_all(b"diff bytes")
+        .unwrap();
+    // Unique size: ruled out by the size stage
+    File::create(dir.path().join("d.txt"))
+        .unwrap()
+        .write_all(b"a much longer and unique file")
+        .unwrap();
+
+    let result = scan_directory_with_stats(
+        dir.path().to_str().unwrap(),
+        &FilterOptions::default(),
+        HashAlgorithm::Xxh3,
+    );
+
+    let summary: Vec<_> = result
+        .stages
+        .iter()
+        .map(|stage| (stage.name, stage.candidates, stage.removed))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![("size", 4, 1), ("sample", 3, 1), ("full", 2, 0)]
+    );
+
+    assert_eq!(result.groups.len(), 1);
+    assert_eq!(result.groups[0].files.len(), 2);
+}
+
+#[test]
+fn test_unreadable_root_is_reported() {
+    let dir = tempdir().unwrap();
+    let missing = dir.path().join("missing");
+
+    let result = scan_directory_with_stats(
+        missing.to_str().unwrap(),
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+    );
+    assert!(result.groups.is_empty());
+    assert_eq!(result.errors.len(), 1);
+    assert!(matches!(&result.errors[0], Error::Vanished { path } if *path == missing));
+}
+
+#[test]
+fn test_groups_carry_file_metadata() {
+    let dir = tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, b"with metadata").unwrap();
+    fs::write(&b, b"with metadata").unwrap();
+    fs::write(dir.path().join("single.txt"), b"no duplicate").unwrap();
+
+    let result =
+        scan_directory_with_stats(dir.path(), &FilterOptions::default(), HashAlgorithm::Sha256);
+
+    // The file without a duplicate forms no group
+    assert_eq!(result.groups.len(), 1);
+    let group = &result.groups[0];
+    assert_eq!(group.size, 13);
+    assert_eq!(group.digest.len(), 64);
+
+    let metadata = fs::metadata(&a).unwrap();
+    let entry = &group.files[0];
+    assert_eq!(entry.path, a);
+    assert_eq!(entry.size, 13);
+    assert_eq!(entry.modified, metadata.modified().ok());
+    assert_eq!((entry.dev, entry.ino), (metadata.dev(), metadata.ino()));
+    assert_eq!(entry.mode, metadata.mode());
+    assert_eq!(group.files[1].path, b);
+}
+
+#[test]
+fn test_hard_links_are_hashed_once_and_marked() {
+    let dir = tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let a_link = dir.path().join("a-link.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, b"linked data").unwrap();
+    fs::hard_link(&a, &a_link).unwrap();
+    fs::write(&b, b"linked data").unwrap();
+
+    let cache = HashCache::new(dir.path().join("cache.json"));
+    let result = scan_directory_with_cache(
+        dir.path(),
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+        &cache,
+    );
+
+    // Two inodes are hashed, but all three names are listed
+    assert_eq!(cache.misses(), 2);
+    assert_eq!(result.stages[0].candidates, 2);
+    assert_eq!(result.groups.len(), 1);
+    let group = &result.groups[0];
+    assert_eq!(group.files.len(), 3);
+    assert_eq!(group.inodes(), 2);
+    assert_eq!(group.reclaimable_bytes(), 11);
+
+    let linked: Vec<_> = group
+        .files
+        .iter()
+        .filter(|file| group.is_linked(file))
+        .map(|file| file.path.clone())
+        .collect();
+    assert_eq!(linked, [a_link, a]);
+}
+
+#[test]
+fn test_names_of_one_inode_are_not_duplicates() {
+    let dir = tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    fs::write(&a, b"only one copy").unwrap();
+    fs::hard_link(&a, dir.path().join("b.txt")).unwrap();
+
+    let result =
+        scan_directory_with_stats(dir.path(), &FilterOptions::default(), HashAlgorithm::Sha256);
+    assert!(result.groups.is_empty());
+}
+
+fn scan_with_symlinks(dir: &Path, symlinks: SymlinkPolicy) -> ScanResult {
+    let filters = FilterOptions {
+        symlinks,
+        ..FilterOptions::default()
+    };
+    scan_directory_with_stats(dir, &filters, HashAlgorithm::Sha256)
+}
+
+#[test]
+fn test_symlink_is_never_a_copy_of_its_target() {
+    let dir = tempdir().unwrap();
+    let target = dir.path().join("target.txt");
+    fs::write(&target, b"only copy").unwrap();
+    symlink(&target, dir.path().join("link.txt")).unwrap();
+    symlink("target.txt", dir.path().join("relative-link.txt")).unwrap();
+
+    for policy in [
+        SymlinkPolicy::Skip,
+        SymlinkPolicy::Follow,
+        SymlinkPolicy::Report,
+    ] {
+        let result = scan_with_symlinks(dir.path(), policy);
+        assert!(
+            result.groups.is_empty(),
+            "{policy} grouped a link with its target"
+        );
+    }
+
+    let reported = scan_with_symlinks(dir.path(), SymlinkPolicy::Report);
+    assert_eq!(reported.symlinks.len(), 2);
+}
+
+#[test]
+fn test_follow_survives_symlink_loops() {
+    let dir = tempdir().unwrap();
+    let nested = dir.path().join("a/b");
+    fs::create_dir_all(&nested).unwrap();
+    symlink(dir.path(), nested.join("loop")).unwrap();
+    symlink(dir.path().join("a"), dir.path().join("a-again")).unwrap();
+
+    let outside = tempdir().unwrap();
+    fs::write(outside.path().join("x.txt"), b"followed").unwrap();
+    fs::write(nested.join("x.txt"), b"followed").unwrap();
+    symlink(outside.path(), dir.path().join("outside")).unwrap();
+
+    let result = scan_with_symlinks(dir.path(), SymlinkPolicy::Follow);
+    assert!(result.errors.is_empty());
+    assert_eq!(result.groups.len(), 1);
+    // Each directory is walked once, under its own name if it has one below the root
+    let paths: Vec<_> = result.groups[0].paths().collect();
+    assert_eq!(
+        paths,
+        [
+            nested.join("x.txt").as_path(),
+            dir.path().join("outside/x.txt").as_path()
+        ]
+    );
+
+    let skipped = scan_with_symlinks(dir.path(), SymlinkPolicy::Skip);
+    assert!(skipped.groups.is_empty());
+}
+
+#[test]
+fn test_follow_lists_files_under_the_link_path() {
+    let dir = tempdir().unwrap();
+    let outside = tempdir().unwrap();
+    write_files(outside.path(), &["target.txt"], b"followed file");
+    write_files(dir.path(), &["copy.txt"], b"followed file");
+    symlink(
+        outside.path().join("target.txt"),
+        dir.path().join("link.txt"),
+    )
+    .unwrap();
+    write_files(
+        outside.path(),
+        &["tree/cache/b.txt", "tree/data/b.txt"],
+        b"followed dir",
+    );
+    write_files(dir.path(), &["b.txt"], b"followed dir");
+    symlink(outside.path().join("tree"), dir.path().join("linked")).unwrap();
+
+    // Relative excludes apply below a linked directory as anywhere else
+    let filters = FilterOptions {
+        symlinks: SymlinkPolicy::Follow,
+        exclude_dirs: compile_globs(&["linked/cache"]).unwrap(),
+        ..FilterOptions::default()
+    };
+    let result = scan_directory_with_stats(dir.path(), &filters, HashAlgorithm::Sha256);
+    assert!(result.errors.is_empty());
+    assert_eq!(
+        grouped_names(&result, dir.path()),
+        ["b.txt", "copy.txt", "link.txt", "linked/data/b.txt"]
+    );
+}
+
+/// Writes `contents` to each of `names` under `dir`, creating parent directories.
+fn write_files(dir: &Path, names: &[&str], contents: &[u8]) {
+    for name in names {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
     }
+}
+
+/// File names, relative to `dir`, of every file in a duplicate group.
+fn grouped_names(result: &ScanResult, dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = result
+        .groups
+        .iter()
+        .flat_map(|group| group.paths())
+        .map(|path| {
+            path.strip_prefix(dir)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_exclude_patterns_prune_the_walk() {
+    let dir = tempdir().unwrap();
+    write_files(
+        dir.path(),
+        &[
+            "src/a.txt",
+            "src/a.tmp",
+            "node_modules/pkg/a.txt",
+            "build/cache/a.txt",
+            "build/out/a.txt",
+        ],
+        b"excluded or not",
+    );
+
+    let filters = FilterOptions {
+        exclude: compile_globs(&["*.tmp"]).unwrap(),
+        exclude_dirs: compile_globs(&["node_modules", "build/cache"]).unwrap(),
+        ..FilterOptions::default()
+    };
+    let result = scan_directory_with_stats(dir.path(), &filters, HashAlgorithm::Sha256);
+
+    assert_eq!(result.stages[0].candidates, 2);
+    assert_eq!(
+        grouped_names(&result, dir.path()),
+        ["build/out/a.txt", "src/a.txt"]
+    );
+}
+
+#[test]
+fn test_ignore_files_are_honoured() {
+    let dir = tempdir().unwrap();
+    write_files(
+        dir.path(),
+        &[
+            "keep/a.txt",
+            "target/a.txt",
+            "logs/a.log",
+            "logs/important.log",
+            "private/a.txt",
+        ],
+        b"ignored or not",
+    );
+    fs::write(dir.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+    fs::write(dir.path().join("logs/.ignore"), "!important.log\n").unwrap();
+    fs::write(dir.path().join(".hashlaserignore"), "private/\n").unwrap();
+
+    // .hashlaserignore applies on its own
+    let result =
+        scan_directory_with_stats(dir.path(), &FilterOptions::default(), HashAlgorithm::Sha256);
+    assert_eq!(
+        grouped_names(&result, dir.path()),
+        [
+            "keep/a.txt",
+            "logs/a.log",
+            "logs/important.log",
+            "target/a.txt"
+        ]
+    );
+
+    // A nested .ignore re-includes what the outer .gitignore excluded
+    let filters = FilterOptions {
+        ignore_files: true,
+        ..FilterOptions::default()
+    };
+    let result = scan_directory_with_stats(dir.path(), &filters, HashAlgorithm::Sha256);
+    assert_eq!(
+        grouped_names(&result, dir.path()),
+        ["keep/a.txt", "logs/important.log"]
+    );
+}
+
+#[test]
+fn test_multiple_roots_find_cross_root_duplicates() {
+    let dir = tempdir().unwrap();
+    write_files(
+        dir.path(),
+        &["backup/a.txt", "home/a.txt"],
+        b"in both roots",
+    );
+    write_files(dir.path(), &["home/b.txt", "home/c.txt"], b"only at home");
+    let roots = [dir.path().join("backup"), dir.path().join("home")];
+
+    let mut result = scan_roots(
+        &roots,
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+        None,
+    );
+    assert_eq!(result.groups.len(), 2);
+    let shared = result
+        .groups
+        .iter()
+        .find(|group| group.spans_roots())
+        .unwrap();
+    let found: Vec<usize> = shared.files.iter().map(|file| file.root).collect();
+    assert_eq!(found, [0, 1]);
+
+    result.retain_cross_root();
+    assert_eq!(
+        grouped_names(&result, dir.path()),
+        ["backup/a.txt", "home/a.txt"]
+    );
+}
+
+#[test]
+fn test_overlapping_roots_count_each_file_once() {
+    let dir = tempdir().unwrap();
+    write_files(dir.path(), &["a.txt", "sub/a.txt"], b"nested roots");
+    let roots = [
+        dir.path().to_path_buf(),
+        dir.path().join("sub"),
+        dir.path().to_path_buf(),
+    ];
+
+    let result = scan_roots(
+        &roots,
+        &FilterOptions::default(),
+        HashAlgorithm::Sha256,
+        None,
+    );
+    assert_eq!(result.groups.len(), 1);
+    let group = &result.groups[0];
+    assert_eq!(group.files.len(), 2);
+
+    // The nested file belongs to the innermost root that contains it
+    let nested = group
+        .files
+        .iter()
+        .find(|file| file.path.ends_with("sub/a.txt"))
+        .unwrap();
+    assert_eq!(nested.root, 1);
+    assert!(group.spans_roots());
+}
+
+#[test]
+fn test_scanner_reports_progress_events() {
+    let dir = tempdir().unwrap();
+    write_files(dir.path(), &["a.txt", "sub/a.txt"], b"twelve bytes");
+    write_files(dir.path(), &["unique.txt"], b"no copy");
+
+    let events = Mutex::new(Vec::new());
+    let result = Scanner::new(&[dir.path()])
+        .on_event(|event| {
+            let name = match event {
+                ScanEvent::DirectoryEntered(_) => "dir".to_string(),
+                ScanEvent::FileDiscovered(_) => "file".to_string(),
+                ScanEvent::StageStarted { name, bytes, .. } => format!("start {name} {bytes}"),
+                ScanEvent::BytesHashed(bytes) => format!("hashed {bytes}"),
+                ScanEvent::StageComplete(stats) => format!("done {}", stats.name),
+                ScanEvent::GroupConfirmed(group) => format!("group {}", group.files.len()),
+            };
+            events.lock().unwrap().push(name);
+        })
+        .run();
+    assert!(!result.cancelled);
+    assert_eq!(result.groups.len(), 1);
+
+    let events = events.into_inner().unwrap();
+    let count = |name: &str| events.iter().filter(|event| *event == name).count();
+    assert_eq!(count("dir"), 2);
+    assert_eq!(count("file"), 3);
+    assert_eq!(count("start sample 24"), 1);
+    assert_eq!(count("start full 24"), 1);
+    assert_eq!(count("hashed 12"), 4);
+    assert_eq!(count("done full"), 1);
+    assert_eq!(events.last().unwrap(), "done full");
+    assert_eq!(count("group 2"), 1);
+}
+
+#[test]
+fn test_cancelled_scan_returns_partial_results() {
+    let dir = tempdir().unwrap();
+    for i in 0..20 {
+        let contents = format!("group {i}");
+        write_files(
+            dir.path(),
+            &[&format!("{i}/a.txt"), &format!("{i}/b.txt")],
+            contents.as_bytes(),
+        );
+    }
+
+    // Cancelled before it starts, nothing is walked
+    let token = CancellationToken::new();
+    token.cancel();
+    let result = Scanner::new(&[dir.path()]).cancel_token(token).run();
+    assert!(result.cancelled);
+    assert!(result.groups.is_empty());
+    assert!(result.stages.is_empty());
+
+    // Cancelled once the first group is confirmed, every group returned is complete
+    let token = CancellationToken::new();
+    let result = Scanner::new(&[dir.path()])
+        .cancel_token(token.clone())
+        .on_event(|event| {
+            if let ScanEvent::GroupConfirmed(_) = event {
+                token.cancel();
+            }
+        })
+        .run();
+    assert!(result.cancelled);
+    assert!(!result.groups.is_empty());
+    assert!(result.groups.iter().all(|group| group.files.len() == 2));
 
-    assert!(found, "Should detect duplicates between file1 and file2");
+    // Cancelled as the full hash starts, that stage rules nothing out
+    let token = CancellationToken::new();
+    let result = Scanner::new(&[dir.path()])
+        .cancel_token(token.clone())
+        .on_event(|event| {
+            if let ScanEvent::StageStarted { name: "full", .. } = event {
+                token.cancel();
+            }
+        })
+        .run();
+    assert!(result.cancelled);
+    let stages: Vec<_> = result
+        .stages
+        .iter()
+        .map(|stage| (stage.name, stage.complete, stage.removed))
+        .collect();
+    assert_eq!(
+        stages,
+        [("size", true, 0), ("sample", true, 0), ("full", false, 0)]
+    );
 }
@@ -0,0 +1,93 @@
+use intelligent_file_deduplicator::filter::FilterOptions;
+use intelligent_file_deduplicator::keep_policy::KeepPolicy;
+use intelligent_file_deduplicator::scanner::{ScanResult, Scanner, SymlinkPolicy};
+use intelligent_file_deduplicator::stats::{ScanStats, human_size};
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn scan(dir: &Path) -> ScanResult {
+    Scanner::new(&[dir])
+        .filters(&FilterOptions::default())
+        .run()
+}
+
+#[test]
+fn test_stats_count_hard_links_once() {
+    let dir = tempdir().unwrap();
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+    fs::create_dir_all(&a).unwrap();
+    fs::create_dir_all(&b).unwrap();
+    fs::write(a.join("1.bin"), vec![7u8; 1000]).unwrap();
+    fs::hard_link(a.join("1.bin"), a.join("link.bin")).unwrap();
+    fs::write(b.join("2.bin"), vec![7u8; 1000]).unwrap();
+    fs::write(b.join("3.bin"), vec![7u8; 1000]).unwrap();
+    fs::write(b.join("unique.bin"), vec![1u8; 10]).unwrap();
+
+    let result = scan(dir.path());
+    assert_eq!(result.files_scanned, 5);
+    assert_eq!(result.bytes_scanned, 3010);
+
+    let stats = ScanStats::from_scan(&result, &KeepPolicy::default(), 10);
+    assert_eq!(stats.groups, 1);
+    // The hard link survives with the kept file and frees nothing
+    assert_eq!(stats.redundant_files, 2);
+    assert_eq!(stats.reclaimable_bytes, 2000);
+    assert_eq!(stats.largest_groups[0].reclaimable_bytes, 2000);
+    assert_eq!(stats.largest_groups[0].keep, a.join("1.bin"));
+
+    assert_eq!(stats.directories.len(), 1);
+    assert_eq!(stats.directories[0].path, b);
+    assert_eq!(stats.directories[0].redundant_files, 2);
+    assert_eq!(stats.directories[0].redundant_bytes, 2000);
+}
+
+#[test]
+fn test_stats_list_top_groups_largest_first() {
+    let dir = tempdir().unwrap();
+    for (name, size) in [("small", 10), ("medium", 100), ("large", 1000)] {
+        fs::write(dir.path().join(format!("{name}1")), vec![size as u8; size]).unwrap();
+        fs::write(dir.path().join(format!("{name}2")), vec![size as u8; size]).unwrap();
+    }
+
+    let stats = ScanStats::from_scan(&scan(dir.path()), &KeepPolicy::default(), 2);
+    assert_eq!(stats.groups, 3);
+    assert_eq!(stats.reclaimable_bytes, 1110);
+    let sizes: Vec<u64> = stats.largest_groups.iter().map(|g| g.size).collect();
+    assert_eq!(sizes, [1000, 100]);
+    assert_eq!(stats.directories.len(), 1);
+}
+
+#[test]
+fn test_followed_symlinks_free_nothing() {
+    let dir = tempdir().unwrap();
+    let (root, outside) = (dir.path().join("root"), dir.path().join("out"));
+    fs::create_dir_all(&root).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    fs::write(root.join("copy"), b"followed").unwrap();
+    fs::write(outside.join("target"), b"followed").unwrap();
+    symlink(outside.join("target"), root.join("link")).unwrap();
+
+    let filters = FilterOptions {
+        symlinks: SymlinkPolicy::Follow,
+        ..FilterOptions::default()
+    };
+    let result = Scanner::new(&[&root]).filters(&filters).run();
+    assert_eq!(result.groups.len(), 1);
+
+    let stats = ScanStats::from_scan(&result, &KeepPolicy::default(), 10);
+    assert_eq!(stats.groups, 1);
+    assert_eq!(stats.redundant_files, 0);
+    assert_eq!(stats.reclaimable_bytes, 0);
+    assert_eq!(stats.largest_groups[0].reclaimable_bytes, 0);
+    assert!(stats.directories.is_empty());
+}
+
+#[test]
+fn test_human_size() {
+    assert_eq!(human_size(0), "0 B");
+    assert_eq!(human_size(1023), "1023 B");
+    assert_eq!(human_size(1536), "1.5 KiB");
+    assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+}
@@ -0,0 +1,52 @@
+use intelligent_file_deduplicator::trash::Trash;
+use std::{
+    fs::{self, File},
+    io::Write,
+};
+use tempfile::tempdir;
+
+#[test]
+fn test_trash_file_writes_trashinfo() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("old report.txt");
+    File::create(&file).unwrap().write_all(b"bye").unwrap();
+
+    let trash = Trash::with_home(dir.path().join("Trash"));
+    let trashed = trash.trash_file(&file).unwrap();
+
+    assert!(!file.exists());
+    assert_eq!(fs::read(&trashed.files_path).unwrap(), b"bye");
+    assert_eq!(
+        trashed.files_path,
+        dir.path()
+            .join("Trash/files/old report.txt")
+            .canonicalize()
+            .unwrap()
+    );
+
+    let info = fs::read_to_string(&trashed.info_path).unwrap();
+    let mut lines = info.lines();
+    assert_eq!(lines.next(), Some("[Trash Info]"));
+    let path_line = lines.next().unwrap();
+    assert!(path_line.starts_with("Path=/"));
+    assert!(path_line.ends_with("/old%20report.txt"));
+    assert!(lines.next().unwrap().starts_with("DeletionDate="));
+}
+
+#[test]
+fn test_trash_name_collisions_get_unique_names() {
+    let dir = tempdir().unwrap();
+    let trash = Trash::with_home(dir.path().join("Trash"));
+
+    let mut names = Vec::new();
+    for sub in ["a", "b"] {
+        let sub_dir = dir.path().join(sub);
+        fs::create_dir(&sub_dir).unwrap();
+        let file = sub_dir.join("same.txt");
+        File::create(&file).unwrap();
+        let trashed = trash.trash_file(&file).unwrap();
+        names.push(trashed.files_path.file_name().unwrap().to_owned());
+    }
+
+    assert_eq!(names, ["same.txt", "same.txt.2"]);
+}